@@ -0,0 +1,69 @@
+//! `#[derive(NdEq)]` for structs whose fields all implement
+//! `ndmachine::NdEq`.
+//!
+//! ```ignore
+//! #[macro_use]
+//! extern crate ndmachine_derive;
+//!
+//! #[derive(Clone, NdEq)]
+//! struct Point {
+//!     x: ndu,
+//!     y: ndu,
+//! }
+//! ```
+//!
+//! The generated `ndeq` is the conjunction of the fields' `ndeq`, built
+//! with `ndbool::all`; a struct without fields compares equal to itself.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Error, Fields, GenericParam, Index};
+
+#[proc_macro_derive(NdEq)]
+pub fn derive_ndeq(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input).unwrap_or_else(|e| {
+        // `Error::to_compile_error` names `::core`, which 2015-edition
+        // crates cannot see.
+        let message = e.to_string();
+        quote_spanned!(e.span()=> compile_error!(#message);)
+    }).into()
+}
+
+fn expand(mut input: DeriveInput) -> Result<TokenStream2, Error> {
+    let fields = match input.data {
+        Data::Struct(ref data) => &data.fields,
+        _ => return Err(Error::new_spanned(&input.ident, "NdEq can only be derived for structs")),
+    };
+    let comparisons: Vec<TokenStream2> = match *fields {
+        Fields::Named(ref fields) => fields.named.iter().map(|f| {
+            let name = &f.ident;
+            quote!(::ndmachine::NdEq::ndeq(&self.#name, &rhs.#name))
+        }).collect(),
+        Fields::Unnamed(ref fields) => (0..fields.unnamed.len()).map(|i| {
+            let index = Index::from(i);
+            quote!(::ndmachine::NdEq::ndeq(&self.#index, &rhs.#index))
+        }).collect(),
+        Fields::Unit => vec![],
+    };
+    for param in &mut input.generics.params {
+        if let GenericParam::Type(ref mut param) = *param {
+            param.bounds.push(parse_quote!(::ndmachine::NdEq));
+        }
+    }
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::ndmachine::NdEq for #name #ty_generics #where_clause {
+            fn ndeq(&self, rhs: &Self) -> ::ndmachine::ndbool {
+                ::ndmachine::ndbool::all(&[#(#comparisons),*])
+            }
+        }
+    })
+}
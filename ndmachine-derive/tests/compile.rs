@@ -0,0 +1,8 @@
+extern crate trybuild;
+
+#[test]
+fn test_compile() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/generic.rs");
+    t.compile_fail("tests/ui/enum.rs");
+}
@@ -0,0 +1,58 @@
+#[macro_use]
+extern crate ndmachine_derive;
+extern crate ndmachine;
+
+use std::process::Command;
+
+use ndmachine::{init, ndassert, ndassert_eq, ndbool, ndu, solve_by, ExternalSolver, NdEq};
+
+#[derive(Clone, NdEq)]
+struct Point {
+    x: ndu,
+    y: ndu,
+}
+
+#[derive(Clone, NdEq)]
+struct Flagged(ndu, ndbool);
+
+#[derive(NdEq)]
+struct Empty;
+
+fn solve() -> bool {
+    solve_by(&ExternalSolver::new(|| Command::new("minisat")))
+}
+
+#[test]
+fn test_named_fields() {
+    init();
+    let p = Point { x: ndu::fresh(4), y: ndu::fresh(4) };
+    let q = Point { x: ndu::fresh(4), y: ndu::fresh(4) };
+    ndassert_eq(p.clone(), q.clone());
+    ndassert_eq(p.x.clone(), ndu::constant(3, 4));
+    ndassert_eq(q.y.clone(), ndu::constant(11, 4));
+    assert!(solve());
+    assert_eq!((p.x.value(), p.y.value()), (3, 11));
+    assert_eq!((q.x.value(), q.y.value()), (3, 11));
+    ndassert(p.y.ndne(&ndu::constant(11, 4)));
+    assert!(!solve());
+}
+
+#[test]
+fn test_tuple_fields() {
+    init();
+    let a = Flagged(ndu::fresh(2), ndbool::fresh());
+    let b = Flagged(ndu::constant(2, 2), ndbool::t());
+    ndassert(a.ndne(&b));
+    ndassert_eq(a.0.clone(), ndu::constant(2, 2));
+    assert!(solve());
+    assert!(!a.1.value());
+}
+
+#[test]
+fn test_unit_struct() {
+    init();
+    ndassert(Empty.ndeq(&Empty));
+    assert!(solve());
+    ndassert(Empty.ndne(&Empty));
+    assert!(!solve());
+}
@@ -0,0 +1,11 @@
+#[macro_use]
+extern crate ndmachine_derive;
+extern crate ndmachine;
+
+#[derive(NdEq)]
+enum Color {
+    Red,
+    Green,
+}
+
+fn main() {}
@@ -0,0 +1,20 @@
+#[macro_use]
+extern crate ndmachine_derive;
+extern crate ndmachine;
+
+use ndmachine::{ndbool, ndu, NdEq};
+
+#[derive(NdEq)]
+struct Pair<T> {
+    first: T,
+    second: T,
+    flag: ndbool,
+}
+
+fn compare(a: &Pair<ndu>, b: &Pair<ndu>) -> ndbool {
+    a.ndeq(b)
+}
+
+fn main() {
+    let _ = compare;
+}
@@ -0,0 +1,207 @@
+//! Reading arrays at a symbolic index, and converting between indices
+//! and one-hot bits.
+
+use {ndassert, ndbool, ndu, NdEq};
+
+/// What `array_read_by` does with an index past the end of the array.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutOfRange {
+    /// The read returns false, or zero for `ndu` elements.
+    Zero,
+    /// The index is asserted to be in range.
+    Assert,
+}
+
+/// Returns `elements[index]`, or false if `index` is out of range.
+///
+/// This is a multiplexer tree with one level of `select` gates per bit of
+/// `index`: about `n` gates in total.
+pub fn array_read(elements: &[ndbool], index: &ndu) -> ndbool {
+    array_read_by(elements, index, OutOfRange::Zero)
+}
+
+/// Like `array_read`, choosing how out-of-range indices are handled.
+pub fn array_read_by(elements: &[ndbool], index: &ndu, out_of_range: OutOfRange) -> ndbool {
+    if out_of_range == OutOfRange::Assert {
+        assert_index(elements.len(), index);
+    }
+    mux(elements.to_vec(), index)
+}
+
+/// Returns `elements[index]`, or zero if `index` is out of range. Elements
+/// of different widths are zero-extended to the widest.
+pub fn array_read_ndu(elements: &[ndu], index: &ndu) -> ndu {
+    array_read_ndu_by(elements, index, OutOfRange::Zero)
+}
+
+/// Like `array_read_ndu`, choosing how out-of-range indices are handled.
+pub fn array_read_ndu_by(elements: &[ndu], index: &ndu, out_of_range: OutOfRange) -> ndu {
+    if out_of_range == OutOfRange::Assert {
+        assert_index(elements.len(), index);
+    }
+    let width = elements.iter().map(|x| x.width()).max().unwrap_or(0);
+    let elements: Vec<ndu> = elements.iter().map(|x| x.clone().zext(width)).collect();
+    ndu {
+        bits: (0..width).map(|i| mux(elements.iter().map(|x| x.bits[i]).collect(), index)).collect(),
+    }
+}
+
+/// Returns the index of the lowest-index true bit, and a flag that is
+/// true exactly when some bit is set.
+///
+/// Lower indices take priority: for bits `[false, true, true]` the index
+/// is 1. When no bit is set the index is zero. The index is
+/// `ceil(log2(n))` bits wide.
+pub fn priority_encoder(bits: &[ndbool]) -> (ndu, ndbool) {
+    let mut width = 0;
+    while (1u128 << width) < bits.len() as u128 {
+        width += 1;
+    }
+    let mut index = ndu::constant(0, width);
+    for (i, &bit) in bits.iter().enumerate().rev() {
+        index = ndu::select(bit, &ndu::constant(i as u64, width), &index);
+    }
+    (index, ndbool::any(bits))
+}
+
+/// Returns `2^width` bits of which exactly the one at position `index` is
+/// true.
+///
+/// Each output compares `index` with a constant, so this is meant for
+/// narrow indices.
+pub fn decode(index: &ndu) -> Vec<ndbool> {
+    assert!(index.width() < 32, "decode of an index this wide is too large");
+    (0..1u64 << index.width())
+        .map(|v| index.ndeq(&ndu::constant(v, index.width())))
+        .collect()
+}
+
+fn assert_index(len: usize, index: &ndu) {
+    if len == 0 {
+        ndassert(ndbool::f());
+    } else {
+        index.assert_in_range(0, len as u64 - 1);
+    }
+}
+
+/// Selects by the index bits from the least significant one up; missing
+/// elements read as false.
+fn mux(mut level: Vec<ndbool>, index: &ndu) -> ndbool {
+    if level.is_empty() {
+        return ndbool::f();
+    }
+    for &bit in &index.bits {
+        if level.len() % 2 == 1 {
+            level.push(ndbool::f());
+        }
+        level = level.chunks(2).map(|pair| ndbool::select(bit, pair[1], pair[0])).collect();
+    }
+    level[0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tests::solve;
+    use {exactly_k, init, ndassert_eq};
+
+    #[test]
+    fn test_array_read() {
+        init();
+        let pattern = [true, false, false, true, true];
+        let elements: Vec<ndbool> = pattern.iter().map(|&b| ndbool::constant(b)).collect();
+        let reads: Vec<_> = (0..8).map(|i| array_read(&elements, &ndu::constant(i, 3))).collect();
+        let narrow = array_read(&elements, &ndu::constant(3, 2));
+        let empty = array_read(&[], &ndu::constant(0, 2));
+        assert!(solve());
+        for (i, r) in reads.iter().enumerate() {
+            assert_eq!(r.value(), i < 5 && pattern[i]);
+        }
+        assert!(narrow.value());
+        assert!(!empty.value());
+    }
+
+    #[test]
+    fn test_array_read_assert() {
+        init();
+        let elements: Vec<ndbool> = (0..5).map(|_| ndbool::fresh()).collect();
+        let index = ndu::fresh(3);
+        ndassert(array_read_by(&elements, &index, OutOfRange::Assert));
+        for &b in &elements {
+            ndassert(!b);
+        }
+        assert!(!solve());
+
+        init();
+        let elements: Vec<ndbool> = (0..5).map(|_| ndbool::fresh()).collect();
+        let index = ndu::fresh(3);
+        ndassert(!array_read_by(&elements, &index, OutOfRange::Assert));
+        for &b in &elements[..4] {
+            ndassert(b);
+        }
+        assert!(solve());
+        assert_eq!(index.value(), 4);
+    }
+
+    #[test]
+    fn test_priority_encoder() {
+        for pattern in 0..64u32 {
+            init();
+            let bits: Vec<ndbool> = (0..6).map(|i| {
+                let b = ndbool::fresh();
+                ndassert_eq(b, ndbool::constant(pattern & (1 << i) != 0));
+                b
+            }).collect();
+            let (index, valid) = priority_encoder(&bits);
+            assert_eq!(index.width(), 3);
+            assert!(solve());
+            assert_eq!(valid.value(), pattern != 0);
+            let expected = if pattern == 0 { 0 } else { pattern.trailing_zeros() as u64 };
+            assert_eq!(index.value(), expected, "pattern {:06b}", pattern);
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        init();
+        let outputs: Vec<_> = (0..8).map(|i| decode(&ndu::constant(i, 3))).collect();
+        assert!(solve());
+        for (i, bits) in outputs.iter().enumerate() {
+            let values: Vec<bool> = bits.iter().map(|b| b.value()).collect();
+            let expected: Vec<bool> = (0..8).map(|j| j == i).collect();
+            assert_eq!(values, expected);
+        }
+
+        init();
+        let index = ndu::fresh(3);
+        let bits = decode(&index);
+        ndassert(!exactly_k(&bits, 1));
+        assert!(!solve());
+
+        init();
+        let index = ndu::fresh(3);
+        let bits = decode(&index);
+        ndassert(!array_read(&bits, &index));
+        assert!(!solve());
+    }
+
+    #[test]
+    fn test_array_read_ndu() {
+        init();
+        let table = [3, 1, 4, 1, 5, 9];
+        let elements: Vec<ndu> = table.iter().map(|&v| ndu::constant(v, 4)).collect();
+        let index = ndu::fresh(3);
+        let read = array_read_ndu(&elements, &index);
+        ndassert_eq(read.clone(), ndu::constant(9, 4));
+        assert!(solve());
+        assert_eq!(index.value(), 5);
+
+        init();
+        let elements = [ndu::constant(2, 2), ndu::constant(12, 4)];
+        let reads: Vec<_> = (0..4).map(|i| array_read_ndu(&elements, &ndu::constant(i, 2))).collect();
+        assert!(solve());
+        let values: Vec<u64> = reads.iter().map(|r| r.value()).collect();
+        assert_eq!(values, vec![2, 12, 0, 0]);
+        assert_eq!(reads[0].width(), 4);
+    }
+}
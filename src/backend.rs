@@ -0,0 +1,247 @@
+//! Solver backends, and the glue needed to read an `Instance` and build an
+//! `Assignment` through the public interface of the `sat` crate.
+
+use std::cell::RefCell;
+use std::io::Cursor;
+
+use sat::{Assignment, Instance};
+use sat::solver::Solver;
+
+use dimacs::{dimacs_format, make_assignment, parse_dimacs, Cnf};
+use SolverError;
+
+use varisat::{self, ExtendFormula};
+
+/// Reads the clauses of an `Instance` by writing it out as DIMACS.
+pub(crate) fn read_instance(instance: &Instance) -> Cnf {
+    let mut buf = vec![];
+    dimacs_format().write_instance(&mut buf, instance);
+    parse_dimacs(&mut Cursor::new(buf)).expect("malformed DIMACS from sat::solver::Dimacs")
+}
+
+/// A pure-Rust SAT solver running in-process, requiring the `varisat`
+/// feature.
+///
+/// Unlike `sat::solver::Dimacs`, no external program is needed, so it also
+/// works where `minisat` is unavailable:
+///
+/// ```ignore
+/// let solver = ndmachine::VarisatSolver::new();
+/// ndmachine::solve_by(&solver);
+/// ```
+pub struct VarisatSolver;
+
+impl VarisatSolver {
+    pub fn new() -> Self {
+        VarisatSolver
+    }
+}
+
+impl Default for VarisatSolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Solver for VarisatSolver {
+    fn solve(&self, instance: &Instance) -> Option<Assignment> {
+        let cnf = read_instance(instance);
+        let mut solver = varisat::Solver::new();
+        run_varisat(&mut solver, &cnf.clauses, &[], cnf.num_vars)
+    }
+}
+
+/// Adds `clauses` to `solver`, solves under `assumptions`, and maps the
+/// model onto an `Assignment` over `num_vars` variables. Variables the
+/// solver never saw are reported as true.
+fn run_varisat(solver: &mut varisat::Solver, clauses: &[Vec<isize>], assumptions: &[isize], num_vars: usize) -> Option<Assignment> {
+    for clause in clauses {
+        let lits: Vec<_> = clause.iter().map(|&l| varisat::Lit::from_dimacs(l)).collect();
+        solver.add_clause(&lits);
+    }
+    let assumptions: Vec<_> = assumptions.iter().map(|&l| varisat::Lit::from_dimacs(l)).collect();
+    solver.assume(&assumptions);
+    match solver.solve() {
+        Ok(true) => {
+            let mut values = vec![true; num_vars + 1];
+            for lit in solver.model().unwrap() {
+                if let Some(v) = values.get_mut(lit.to_dimacs().unsigned_abs()) {
+                    *v = lit.is_positive();
+                }
+            }
+            Some(make_assignment(num_vars, |v| values[v]))
+        }
+        Ok(false) => None,
+        Err(e) => SolverError::raise(format!("varisat failed: {}", e)),
+    }
+}
+
+/// An in-process solver that stays warm between calls.
+///
+/// Each call only feeds the clauses added since the previous call, so the
+/// learnt clauses of earlier solves are kept. This pays off for loops that
+/// add a few clauses and re-solve, like `solutions` and `minimize`.
+///
+/// It is meant to be used with a single, growing instance. If the instance
+/// does not extend the clauses seen so far (e.g. after `reset`), it starts
+/// over from scratch.
+///
+/// The unit clauses at the end of the instance, where the selectors of
+/// tags and the assumptions of `solve_under_assumptions` are placed, are
+/// passed as varisat assumptions instead of being added, so that they can
+/// change from one call to the next without losing the state.
+pub struct IncrementalSolver {
+    state: RefCell<IncrementalState>,
+}
+
+struct IncrementalState {
+    solver: varisat::Solver<'static>,
+    shipped: Vec<Vec<isize>>,
+}
+
+impl IncrementalState {
+    fn new() -> Self {
+        IncrementalState {
+            solver: varisat::Solver::new(),
+            shipped: vec![],
+        }
+    }
+}
+
+impl IncrementalSolver {
+    pub fn new() -> Self {
+        IncrementalSolver {
+            state: RefCell::new(IncrementalState::new()),
+        }
+    }
+}
+
+impl Default for IncrementalSolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Solver for IncrementalSolver {
+    fn solve(&self, instance: &Instance) -> Option<Assignment> {
+        let cnf = read_instance(instance);
+        let mut state = self.state.borrow_mut();
+        if !cnf.clauses.starts_with(&state.shipped) {
+            *state = IncrementalState::new();
+        }
+        let shipped = state.shipped.len();
+        let units = cnf.clauses[shipped..].iter().rev().take_while(|c| c.len() == 1).count();
+        let (clauses, units) = cnf.clauses.split_at(cnf.clauses.len() - units);
+        let assumptions: Vec<isize> = units.iter().map(|c| c[0]).collect();
+        state.shipped.extend_from_slice(&clauses[shipped..]);
+        run_varisat(&mut state.solver, &clauses[shipped..], &assumptions, cnf.num_vars)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {init, minimize_value, ndassert, ndassert_tagged, ndbool, ndu, reset, solve_by, solve_under_assumptions, stats, NdEq, NdOrd, SolveResult};
+
+    #[test]
+    fn test_read_instance() {
+        let mut instance = Instance::new();
+        let a = instance.fresh_var();
+        let b = instance.fresh_var();
+        instance.assert_any(&[a, !b]);
+        instance.assert_any(&[b]);
+        let cnf = read_instance(&instance);
+        assert_eq!(cnf.num_vars, 2);
+        assert_eq!(cnf.clauses, vec![vec![1, -2], vec![2]]);
+    }
+
+    #[test]
+    fn test_make_assignment() {
+        let mut instance = Instance::new();
+        let a = instance.fresh_var();
+        let b = instance.fresh_var();
+        let assignment = make_assignment(2, |v| v == 2);
+        assert!(!assignment.get(a));
+        assert!(assignment.get(!a));
+        assert!(assignment.get(b));
+    }
+
+    #[test]
+    fn test_varisat_solver() {
+        init();
+        let solver = VarisatSolver::new();
+        let b0 = ndbool::fresh();
+        let b1 = ndbool::fresh();
+        let unused = ndbool::fresh();
+        ndassert(b0 ^ b1);
+        ndassert(!b1);
+        assert!(solve_by(&solver));
+        assert!(b0.value() && !b1.value());
+        assert!(unused.try_value().is_some());
+        ndassert(!b0);
+        assert!(!solve_by(&solver));
+    }
+
+    #[test]
+    fn test_incremental_solver() {
+        init();
+        let solver = IncrementalSolver::new();
+        let x = ndu::fresh(6);
+        let y = ndu::fresh(6);
+        ndassert(x.clone().mul_wide(y.clone()).ndeq(&ndu::constant(35, 12)));
+        ndassert(x.ndgt(&ndu::constant(1, 6)) & y.ndgt(&ndu::constant(1, 6)));
+        assert!(solve_by(&solver));
+        assert_eq!(x.value() * y.value(), 35);
+        ndassert(x.ndlt(&y));
+        assert!(solve_by(&solver));
+        assert_eq!((x.value(), y.value()), (5, 7));
+        ndassert(x.ndgt(&y));
+        assert!(!solve_by(&solver));
+    }
+
+    #[test]
+    fn test_incremental_solver_reset() {
+        init();
+        let solver = IncrementalSolver::new();
+        let b = ndbool::fresh();
+        ndassert(b);
+        ndassert(!b);
+        assert!(!solve_by(&solver));
+        reset();
+        let b = ndbool::fresh();
+        ndassert(!b);
+        assert!(solve_by(&solver));
+        assert!(!b.value());
+    }
+
+    #[test]
+    fn test_incremental_solver_units() {
+        init();
+        let solver = IncrementalSolver::new();
+        let a = ndbool::fresh();
+        let b = ndbool::fresh();
+        ndassert(a | b);
+        ndassert_tagged(!a, "not a");
+        assert!(solve_by(&solver));
+        assert!(!a.value() && b.value());
+        assert_eq!(solver.state.borrow().shipped.len(), stats().num_clauses - 1);
+        assert_eq!(solve_under_assumptions(&solver, &[!b]), SolveResult::Unsat);
+        // A clause known only to the warm solver shows that it was kept.
+        solver.state.borrow_mut().solver.add_clause(&[varisat::Lit::from_dimacs(-2)]);
+        assert!(!solve_by(&solver));
+        assert_eq!(solver.state.borrow().shipped.len(), stats().num_clauses - 1);
+    }
+
+    #[test]
+    fn test_incremental_solver_minimize() {
+        init();
+        let solver = IncrementalSolver::new();
+        let x = ndu::fresh(6);
+        ndassert(x.ndge(&ndu::constant(17, 6)));
+        assert_eq!(minimize_value(&solver, &x, None, None), (Some(17), true));
+        let shipped = solver.state.borrow().shipped.len();
+        assert!(solve_by(&solver));
+        assert_eq!(solver.state.borrow().shipped.len(), shipped);
+        assert_eq!(stats().num_clauses, shipped);
+    }
+}
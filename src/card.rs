@@ -0,0 +1,524 @@
+use std::collections::VecDeque;
+
+use ndint::{full_adder, half_adder};
+use {ndbool, ndu, NdEq, NdMachine, NdOrd};
+
+/// Returns an `ndbool` that is true exactly when at most `k` of `bits` are
+/// true.
+///
+/// This builds a unary counter circuit where `s[j]` means "more than `j` of
+/// the bits seen so far are true", introducing about `2 * n * (k + 1)`
+/// auxiliary variables. For `k == 1` it tracks only whether one and whether
+/// two of the bits seen so far are true, introducing `2n - 3` auxiliary
+/// variables and `7n - 11` clauses.
+pub fn at_most_k(bits: &[ndbool], k: usize) -> ndbool {
+    if k >= bits.len() {
+        return ndbool::t();
+    }
+    if k == 1 {
+        return at_most_one(bits);
+    }
+    let mut s: Vec<ndbool> = vec![];
+    for &x in bits {
+        let mut next = Vec::with_capacity(k + 1);
+        for j in 0..(k + 1).min(s.len() + 1) {
+            let carried = if j == 0 { x } else { x & s[j - 1] };
+            next.push(match s.get(j) {
+                Some(&prev) => prev | carried,
+                None => carried,
+            });
+        }
+        s = next;
+    }
+    !s[k]
+}
+
+/// `at_most_k` for `k == 1` and at least two bits.
+fn at_most_one(bits: &[ndbool]) -> ndbool {
+    // Before bits[i]: seen means one of bits[..i] is true, twice means two.
+    let mut seen = bits[0];
+    let mut twice = bits[0] & bits[1];
+    for i in 2..bits.len() {
+        seen |= bits[i - 1];
+        // twice implies seen, so a true bits[i] makes twice equal to seen.
+        twice = ndbool::select(bits[i], seen, twice);
+    }
+    !twice
+}
+
+/// Returns an `ndbool` that is true exactly when at least `k` of `bits` are
+/// true, encoded as at most `n - k` of the negated bits.
+pub fn at_least_k(bits: &[ndbool], k: usize) -> ndbool {
+    if k > bits.len() {
+        return ndbool::f();
+    }
+    let negated: Vec<ndbool> = bits.iter().map(|&b| !b).collect();
+    at_most_k(&negated, bits.len() - k)
+}
+
+/// Returns an `ndbool` that is true exactly when exactly `k` of `bits` are
+/// true.
+pub fn exactly_k(bits: &[ndbool], k: usize) -> ndbool {
+    at_least_k(bits, k) & at_most_k(bits, k)
+}
+
+/// Counts the true bits, returning an `ndu` of width `ceil(log2(n + 1))`.
+///
+/// Bits of equal weight are reduced by full and half adders in FIFO order,
+/// which forms a Wallace-style tree of logarithmic depth.
+pub fn popcount(bits: &[ndbool]) -> ndu {
+    let mut width = 0;
+    while (1u128 << width) <= bits.len() as u128 {
+        width += 1;
+    }
+    let mut columns: Vec<VecDeque<ndbool>> = vec![bits.iter().cloned().collect()];
+    let mut result = Vec::with_capacity(width);
+    let mut w = 0;
+    while w < columns.len() {
+        let mut carries = VecDeque::new();
+        while columns[w].len() > 1 {
+            let a = columns[w].pop_front().unwrap();
+            let b = columns[w].pop_front().unwrap();
+            let (sum, carry) = match columns[w].pop_front() {
+                Some(c) => full_adder(a, b, c),
+                None => half_adder(a, b),
+            };
+            columns[w].push_back(sum);
+            carries.push_back(carry);
+        }
+        if !carries.is_empty() {
+            columns.push(carries);
+        }
+        result.push(columns[w].pop_front().unwrap_or_else(ndbool::f));
+        w += 1;
+    }
+    ndu { bits: result }.resize(width)
+}
+
+/// Returns `(a, b)` if `swap` is false and `(b, a)` if it is true, with
+/// two `select` gates: the exchange element of sorting and permutation
+/// networks. With `swap = !a & b` it orders the pair like the comparators
+/// of `sort_bits`.
+pub fn compare_swap(a: ndbool, b: ndbool, swap: ndbool) -> (ndbool, ndbool) {
+    (ndbool::select(swap, b, a), ndbool::select(swap, a, b))
+}
+
+/// `compare_swap` on integers, with two `select` gates per bit; with
+/// `swap = a.ndlt(&b)` the larger value comes first. Both operands must
+/// have the same width.
+pub fn compare_swap_ndu(a: &ndu, b: &ndu, swap: ndbool) -> (ndu, ndu) {
+    assert_eq!(a.width(), b.width(), "ndu width mismatch");
+    (ndu::select(swap, b, a), ndu::select(swap, a, b))
+}
+
+/// Sorts `bits` descending, all trues before all falses, with Batcher's
+/// odd-even merge sorting network.
+///
+/// Each comparator is an `|` and an `&` of two bits, and there are
+/// `O(n log^2 n)` of them. Output `k` is true exactly when more than `k`
+/// inputs are true, so `sort_bits(bits)[k - 1]` is an alternative encoding
+/// of `at_least_k(bits, k)`.
+pub fn sort_bits(bits: &[ndbool]) -> Vec<ndbool> {
+    let mut bits = bits.to_vec();
+    let n = bits.len();
+    // Comparators reaching past `n` would meet padding falses and keep the
+    // order, so they are skipped.
+    let mut p = 1;
+    while p < n {
+        let mut k = p;
+        while k >= 1 {
+            let mut j = k % p;
+            while j + k < n {
+                for i in 0..k.min(n - j - k) {
+                    if (i + j) / (2 * p) == (i + j + k) / (2 * p) {
+                        let (a, b) = (bits[i + j], bits[i + j + k]);
+                        bits[i + j] = a | b;
+                        bits[i + j + k] = a & b;
+                    }
+                }
+                j += 2 * k;
+            }
+            k /= 2;
+        }
+        p *= 2;
+    }
+    bits
+}
+
+/// Returns an `ndbool` that is true exactly when the weights of the true
+/// terms sum to at most `bound`, as in `3a + 2b + c <= 5`.
+///
+/// The sum is built as an `ndu` wide enough for the total weight, adding
+/// each weight under its term, and compared against the bound.
+pub fn pb_le(terms: &[(u64, ndbool)], bound: u64) -> ndbool {
+    let (sum, total) = weighted_sum(terms);
+    if bound >= total {
+        return ndbool::t();
+    }
+    let width = sum.width();
+    sum.ndle(&ndu::constant(bound, width))
+}
+
+/// Returns an `ndbool` that is true exactly when the weights of the true
+/// terms sum to at least `bound`. See `pb_le`.
+pub fn pb_ge(terms: &[(u64, ndbool)], bound: u64) -> ndbool {
+    let (sum, total) = weighted_sum(terms);
+    if bound > total {
+        return ndbool::f();
+    }
+    let width = sum.width();
+    sum.ndge(&ndu::constant(bound, width))
+}
+
+/// Returns an `ndbool` that is true exactly when the weights of the true
+/// terms sum to `bound`. See `pb_le`.
+pub fn pb_eq(terms: &[(u64, ndbool)], bound: u64) -> ndbool {
+    let (sum, total) = weighted_sum(terms);
+    if bound > total {
+        return ndbool::f();
+    }
+    let width = sum.width();
+    sum.ndeq(&ndu::constant(bound, width))
+}
+
+/// The sum of the weights of the true terms, together with the total weight
+/// that bounds it.
+pub(crate) fn weighted_sum(terms: &[(u64, ndbool)]) -> (ndu, u64) {
+    let total = terms.iter().fold(0u64, |acc, &(w, _)| acc.checked_add(w).expect("total weight overflows u64"));
+    let width = 64 - total.leading_zeros() as usize;
+    let sum = terms.iter().fold(ndu::constant(0, width), |acc, &(w, b)| {
+        let f = ndbool::f();
+        let addend = (0..width).map(|i| if w >> i & 1 != 0 { b } else { f }).collect();
+        acc + ndu { bits: addend }
+    });
+    (sum, total)
+}
+
+/// Asserts that exactly one of `bits` is true.
+pub fn assert_exactly_one(bits: &[ndbool]) {
+    NdMachine::with(|machine| machine.assert_exactly_one(bits))
+}
+
+/// Asserts that at most `k` of `bits` are true.
+///
+/// This uses the sequential counter encoding of Sinz (2005), introducing
+/// `k * (n - 1)` auxiliary variables. For `k == 1` it degenerates to the
+/// ladder encoding with `n - 1` auxiliary variables and `3n - 4` clauses.
+pub fn assert_at_most_k(bits: &[ndbool], k: usize) {
+    NdMachine::with(|machine| machine.assert_at_most_k(bits, k))
+}
+
+impl NdMachine {
+    /// Asserts that exactly one of `bits` is true.
+    pub fn assert_exactly_one(&mut self, bits: &[ndbool]) {
+        let lits: Vec<_> = bits.iter().map(|&b| self.lit(b)).collect();
+        self.add_clause(&lits);
+        self.assert_at_most_one(bits);
+    }
+    /// Asserts that at most `k` of `bits` are true. See `assert_at_most_k`.
+    pub fn assert_at_most_k(&mut self, bits: &[ndbool], k: usize) {
+        self.check_owned(bits);
+        let n = bits.len();
+        if k >= n {
+            return;
+        }
+        if k == 0 {
+            for x in bits {
+                self.add_clause(&[!x.0]);
+            }
+            return;
+        }
+        if k == 1 {
+            self.assert_at_most_one(bits);
+            return;
+        }
+        // s[j] of stage i: more than j of bits[..=i] are true.
+        let mut prev: Vec<ndbool> = vec![];
+        for (i, x) in bits.iter().enumerate() {
+            if i + 1 == n {
+                self.add_clause(&[!x.0, !prev[k - 1].0]);
+                break;
+            }
+            let s: Vec<ndbool> = (0..k).map(|_| self.fresh()).collect();
+            self.add_clause(&[!x.0, s[0].0]);
+            if i == 0 {
+                for sj in &s[1..] {
+                    self.add_clause(&[!sj.0]);
+                }
+            } else {
+                self.add_clause(&[!prev[0].0, s[0].0]);
+                for j in 1..k {
+                    self.add_clause(&[!x.0, !prev[j - 1].0, s[j].0]);
+                    self.add_clause(&[!prev[j].0, s[j].0]);
+                }
+                self.add_clause(&[!x.0, !prev[k - 1].0]);
+            }
+            prev = s;
+        }
+    }
+    fn assert_at_most_one(&mut self, bits: &[ndbool]) {
+        let n = bits.len();
+        let mut seen: Option<ndbool> = None;
+        for (i, x) in bits.iter().enumerate() {
+            if let Some(seen) = seen {
+                self.add_clause(&[!x.0, !seen.0]);
+            }
+            if i + 1 == n {
+                break;
+            }
+            let s = self.fresh();
+            self.add_clause(&[!x.0, s.0]);
+            if let Some(seen) = seen {
+                self.add_clause(&[!seen.0, s.0]);
+            }
+            seen = Some(s);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tests::{solve, solver};
+    use {init, ndassert, ndassert_eq, solutions, stats, NdOrd};
+
+    fn fixed(pattern: u32, n: usize) -> Vec<ndbool> {
+        (0..n).map(|i| {
+            let b = ndbool::fresh();
+            ndassert_eq(b, ndbool::constant(pattern & (1 << i) != 0));
+            b
+        }).collect()
+    }
+
+    #[test]
+    fn test_at_most_k() {
+        for pattern in 0..32u32 {
+            init();
+            let bits = fixed(pattern, 5);
+            let results: Vec<_> = (0..7).map(|k| at_most_k(&bits, k)).collect();
+            assert!(solve());
+            for (k, result) in results.iter().enumerate() {
+                assert_eq!(result.value(), pattern.count_ones() as usize <= k);
+            }
+        }
+    }
+
+    #[test]
+    fn test_at_most_one_size() {
+        for n in 2..7 {
+            init();
+            let bits: Vec<_> = (0..n).map(|_| ndbool::fresh()).collect();
+            at_most_k(&bits, 1);
+            assert_eq!(stats().num_variables - n, 2 * n - 3);
+            assert_eq!(stats().num_clauses, 7 * n - 11);
+            init();
+            let bits: Vec<_> = (0..n).map(|_| ndbool::fresh()).collect();
+            assert_at_most_k(&bits, 1);
+            assert_eq!(stats().num_variables - n, n - 1);
+            assert_eq!(stats().num_clauses, 3 * n - 4);
+        }
+    }
+
+    #[test]
+    fn test_at_least_exactly_k() {
+        for pattern in 0..32u32 {
+            init();
+            let bits = fixed(pattern, 5);
+            let results: Vec<_> = (0..7).map(|k| (at_least_k(&bits, k), exactly_k(&bits, k))).collect();
+            assert!(solve());
+            let count = pattern.count_ones() as usize;
+            for (k, &(at_least, exactly)) in results.iter().enumerate() {
+                assert_eq!(at_least.value(), count >= k);
+                assert_eq!(exactly.value(), count == k);
+            }
+        }
+    }
+
+    #[test]
+    fn test_assert_exactly_one() {
+        init();
+        let bits: Vec<_> = (0..4).map(|_| ndbool::fresh()).collect();
+        assert_exactly_one(&bits);
+        let solver = solver();
+        let all: Vec<_> = solutions(&solver, &bits).collect();
+        assert_eq!(all.len(), 4);
+        for model in all {
+            assert_eq!(model.iter().filter(|&&v| v).count(), 1);
+        }
+    }
+
+    #[test]
+    fn test_popcount() {
+        init();
+        let patterns = [0u32, 1, 0x80, 0xff, 0x5a5a, 0x1234, 0xffff];
+        let counts: Vec<_> = patterns.iter().map(|&p| (popcount(&fixed(p, 16)), p)).collect();
+        assert!(solve());
+        for (count, pattern) in counts {
+            assert_eq!(count.width(), 5);
+            assert_eq!(count.value(), pattern.count_ones() as u64);
+        }
+    }
+
+    #[test]
+    fn test_popcount_width() {
+        init();
+        assert_eq!(popcount(&[]).width(), 0);
+        assert_eq!(popcount(&fixed(1, 1)).width(), 1);
+        assert_eq!(popcount(&fixed(3, 3)).width(), 2);
+        assert_eq!(popcount(&fixed(15, 4)).width(), 3);
+        assert_eq!(popcount(&fixed(127, 7)).width(), 3);
+    }
+
+    #[test]
+    fn test_popcount_bound() {
+        init();
+        let bits: Vec<_> = (0..8).map(|_| ndbool::fresh()).collect();
+        ndassert(popcount(&bits).ndle(&ndu::constant(3, 4)));
+        ndassert(popcount(&bits).ndge(&ndu::constant(3, 4)));
+        assert!(solve());
+        assert_eq!(bits.iter().filter(|b| b.value()).count(), 3);
+    }
+
+    #[test]
+    fn test_sort_bits() {
+        for pattern in 0..64u32 {
+            init();
+            let bits = fixed(pattern, 6);
+            let sorted = sort_bits(&bits);
+            let at_least: Vec<_> = (0..6).map(|k| at_least_k(&bits, k + 1)).collect();
+            assert!(solve());
+            let count = pattern.count_ones() as usize;
+            let values: Vec<_> = sorted.iter().map(|b| b.value()).collect();
+            assert_eq!(values, (0..6).map(|k| k < count).collect::<Vec<_>>());
+            for k in 0..6 {
+                assert_eq!(sorted[k].value(), at_least[k].value());
+            }
+        }
+    }
+
+    #[test]
+    fn test_compare_swap() {
+        init();
+        let mut cases = vec![];
+        for pattern in 0..8u32 {
+            let [a, b, swap] = [0, 1, 2].map(|i| ndbool::constant(pattern & (1 << i) != 0));
+            cases.push((pattern, compare_swap(a, b, swap)));
+        }
+        assert!(solve());
+        for (pattern, (x, y)) in cases {
+            let (a, b) = (pattern & 1 != 0, pattern & 2 != 0);
+            let expected = if pattern & 4 != 0 { (b, a) } else { (a, b) };
+            assert_eq!((x.value(), y.value()), expected, "pattern {:03b}", pattern);
+        }
+    }
+
+    #[test]
+    fn test_compare_swap_network() {
+        // Batcher's network for four inputs, as used by `sort_bits`.
+        let network = [(0, 1), (2, 3), (0, 2), (1, 3), (1, 2)];
+        for pattern in 0..16u32 {
+            init();
+            let bits = fixed(pattern, 4);
+            let mut swapped = bits.clone();
+            for &(i, j) in &network {
+                let (a, b) = (swapped[i], swapped[j]);
+                let (x, y) = compare_swap(a, b, !a & b);
+                swapped[i] = x;
+                swapped[j] = y;
+            }
+            let sorted = sort_bits(&bits);
+            assert!(solve());
+            let values: Vec<_> = swapped.iter().map(|b| b.value()).collect();
+            assert_eq!(values, sorted.iter().map(|b| b.value()).collect::<Vec<_>>());
+        }
+
+        init();
+        let values = [5, 12, 0, 9];
+        let mut xs: Vec<ndu> = values.iter().map(|&v| ndu::constant(v, 4)).collect();
+        for &(i, j) in &network {
+            let swap = xs[i].ndlt(&xs[j]);
+            let (x, y) = compare_swap_ndu(&xs[i], &xs[j], swap);
+            xs[i] = x;
+            xs[j] = y;
+        }
+        assert!(solve());
+        assert_eq!(xs.iter().map(|x| x.value()).collect::<Vec<_>>(), [12, 9, 5, 0]);
+    }
+
+    #[test]
+    fn test_sort_bits_cardinality() {
+        init();
+        assert!(sort_bits(&[]).is_empty());
+        let bits: Vec<_> = (0..11).map(|_| ndbool::fresh()).collect();
+        let sorted = sort_bits(&bits);
+        ndassert(sorted[3] & !sorted[4]);
+        ndassert(bits[0] & !bits[10]);
+        assert!(solve());
+        assert_eq!(bits.iter().filter(|b| b.value()).count(), 4);
+        assert!(bits[0].value() && !bits[10].value());
+    }
+
+    #[test]
+    fn test_pb() {
+        for pattern in 0..16u32 {
+            init();
+            let bits = fixed(pattern, 4);
+            let weights = [3, 2, 1, 5];
+            let terms: Vec<_> = weights.iter().cloned().zip(bits).collect();
+            let sum: u64 = (0..4).filter(|&i| pattern & (1 << i) != 0).map(|i| weights[i]).sum();
+            let results: Vec<_> = (0..13).map(|k| (pb_le(&terms, k), pb_ge(&terms, k), pb_eq(&terms, k))).collect();
+            assert!(solve());
+            for (k, &(le, ge, eq)) in results.iter().enumerate() {
+                let k = k as u64;
+                assert_eq!((le.value(), ge.value(), eq.value()), (sum <= k, sum >= k, sum == k));
+            }
+        }
+    }
+
+    #[test]
+    fn test_pb_knapsack() {
+        // (weight, value); the best value within weight 10 is 13, from the
+        // items of weight 5 and 4 or of weight 6 and 4.
+        let items = [(5, 7), (4, 6), (6, 7), (3, 2)];
+        let knapsack = |value: u64| {
+            init();
+            let take: Vec<_> = items.iter().map(|_| ndbool::fresh()).collect();
+            let weights: Vec<_> = items.iter().zip(&take).map(|(&(w, _), &b)| (w, b)).collect();
+            let values: Vec<_> = items.iter().zip(&take).map(|(&(_, v), &b)| (v, b)).collect();
+            ndassert(pb_le(&weights, 10));
+            ndassert(pb_ge(&values, value));
+            if !solve() {
+                return false;
+            }
+            let chosen = || items.iter().zip(&take).filter(|&(_, b)| b.value());
+            assert!(chosen().map(|(&(w, _), _)| w).sum::<u64>() <= 10);
+            assert!(chosen().map(|(&(_, v), _)| v).sum::<u64>() >= value);
+            true
+        };
+        assert!(knapsack(13));
+        assert!(!knapsack(14));
+    }
+
+    #[test]
+    fn test_assert_at_most_k() {
+        for pattern in 0..32u32 {
+            for k in 0..6 {
+                init();
+                let bits = fixed(pattern, 5);
+                assert_at_most_k(&bits, k);
+                assert_eq!(solve(), pattern.count_ones() as usize <= k);
+            }
+        }
+    }
+
+    #[test]
+    fn test_assert_at_most_k_free() {
+        init();
+        let bits: Vec<_> = (0..6).map(|_| ndbool::fresh()).collect();
+        assert_at_most_k(&bits, 2);
+        ndassert(bits[1] & bits[4]);
+        assert!(solve());
+        let count = bits.iter().filter(|b| b.value()).count();
+        assert_eq!(count, 2);
+    }
+}
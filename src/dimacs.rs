@@ -0,0 +1,403 @@
+//! Writing and reading CNF in DIMACS format, and the DIMACS reader and
+//! writer of `sat::solver::Dimacs`.
+
+use std::fs::File;
+#[cfg(any(feature = "varisat", feature = "serde"))]
+use std::io::Cursor;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::process::Command;
+
+#[cfg(any(feature = "varisat", feature = "serde"))]
+use sat::Assignment;
+use sat::solver::Dimacs;
+
+use {ndbool, Lit, NdMachine};
+
+/// A `Dimacs` solver used only for its reader and writer.
+pub(crate) fn dimacs_format() -> Dimacs<fn() -> Command> {
+    fn no_command() -> Command {
+        unreachable!()
+    }
+    Dimacs::new(no_command as fn() -> Command)
+}
+
+/// A CNF as DIMACS variable count and signed, 1-based literals.
+pub(crate) struct Cnf {
+    pub num_vars: usize,
+    pub clauses: Vec<Vec<isize>>,
+    /// Variable names from `c <i> <name>` comments.
+    pub names: Vec<(usize, String)>,
+}
+
+/// Parses a DIMACS CNF. Clauses may span lines; each is terminated by `0`.
+/// Comment lines are skipped, except that `c <i> <name>` as written by
+/// `export_dimacs` names variable `i`.
+pub(crate) fn parse_dimacs<R: BufRead>(reader: &mut R) -> io::Result<Cnf> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let mut num_vars = None;
+    let mut clauses = vec![];
+    let mut clause = vec![];
+    let mut names = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if let Some(comment) = line.strip_prefix('c') {
+            let mut fields = comment.trim_start().splitn(2, char::is_whitespace);
+            if let (Some(Ok(var)), Some(name)) = (fields.next().map(str::parse::<usize>), fields.next()) {
+                names.push((var, name.trim().to_string()));
+            }
+            continue;
+        }
+        if line.is_empty() || line.starts_with('%') {
+            continue;
+        }
+        if line.starts_with('p') {
+            let fields: Vec<_> = line.split_whitespace().collect();
+            if fields.len() != 4 || fields[1] != "cnf" {
+                return Err(invalid(format!("bad problem line: {}", line)));
+            }
+            num_vars = Some(fields[2].parse().map_err(|_| invalid(format!("bad variable count: {}", fields[2])))?);
+            continue;
+        }
+        for tok in line.split_whitespace() {
+            let lit: isize = tok.parse().map_err(|_| invalid(format!("bad literal: {}", tok)))?;
+            if lit == 0 {
+                clauses.push(std::mem::take(&mut clause));
+            } else {
+                clause.push(lit);
+            }
+        }
+    }
+    if !clause.is_empty() {
+        clauses.push(clause);
+    }
+    let max_var = clauses.iter().flat_map(|c| c.iter()).map(|l| l.unsigned_abs()).max().unwrap_or(0);
+    let num_vars = num_vars.unwrap_or(max_var);
+    if max_var > num_vars {
+        return Err(invalid(format!("literal {} exceeds variable count {}", max_var, num_vars)));
+    }
+    names.retain(|&(var, ref name)| (1..=num_vars).contains(&var) && !name.is_empty());
+    Ok(Cnf { num_vars, clauses, names })
+}
+
+/// Builds an `Assignment` from the values of variables `1..=num_vars`.
+#[cfg(any(feature = "varisat", feature = "serde"))]
+pub(crate) fn make_assignment<F: Fn(usize) -> bool>(num_vars: usize, value: F) -> Assignment {
+    let mut text = String::from("SAT\n");
+    for v in 1..num_vars + 1 {
+        if !value(v) {
+            text.push('-');
+        }
+        text.push_str(&v.to_string());
+        text.push(' ');
+    }
+    text.push_str("0\n");
+    dimacs_format().read_solution(&mut Cursor::new(text), num_vars).unwrap()
+}
+
+/// Writes the CNF of the thread-local machine. See `NdMachine::export_dimacs`.
+pub fn export_dimacs<W: Write>(w: &mut W) -> io::Result<()> {
+    NdMachine::with(|machine| machine.export_dimacs(w))
+}
+
+/// Writes the thread-local machine with its soft constraints. See
+/// `NdMachine::export_wcnf`.
+pub fn export_wcnf<W: Write>(w: &mut W) -> io::Result<()> {
+    NdMachine::with(|machine| machine.export_wcnf(w))
+}
+
+impl NdMachine {
+    /// Writes the CNF with the soft constraints of `assert_soft` in the
+    /// weighted DIMACS format read by MaxSAT solvers, under the header
+    /// `p wcnf <vars> <clauses> <top>`.
+    ///
+    /// Each clause is prefixed by its weight. The hard clauses, numbered as
+    /// in `export_dimacs`, weigh `top`, one more than all soft weights
+    /// together.
+    pub fn export_wcnf<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let top = self.soft.iter().fold(1u64, |acc, &(_, weight)| acc.saturating_add(weight));
+        let hard = self.clauses().map(|c| (top, c));
+        let soft = self.soft.iter().map(|(lit, weight)| (*weight, std::slice::from_ref(lit)));
+        let clauses: Vec<(u64, &[Lit])> = hard.chain(soft).collect();
+        writeln!(w, "p wcnf {} {} {}", self.num_vars, clauses.len(), top)?;
+        for (weight, clause) in clauses {
+            write!(w, "{} ", weight)?;
+            for lit in clause {
+                write!(w, "{} ", lit.to_dimacs())?;
+            }
+            writeln!(w, "0")?;
+        }
+        Ok(())
+    }
+    /// Writes the CNF in DIMACS format, starting with the
+    /// `p cnf <vars> <clauses>` header.
+    ///
+    /// Variable `i` (counting from 1) is the `i`-th variable created on the
+    /// machine. The selectors of tagged assertions are written as unit
+    /// clauses, so the output is the instance every solve sees. Variables
+    /// created by `named` are listed before the header as `c <i> <name>`.
+    pub fn export_dimacs<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut names: Vec<_> = self.names.iter().collect();
+        names.sort();
+        for (var, name) in names {
+            writeln!(w, "c {} {}", var + 1, name)?;
+        }
+        writeln!(w, "p cnf {} {}", self.num_vars, self.clauses().count())?;
+        for clause in self.clauses() {
+            for lit in clause {
+                write!(w, "{} ", lit.to_dimacs())?;
+            }
+            writeln!(w, "0")?;
+        }
+        Ok(())
+    }
+    /// The clauses of `export_dimacs`, one per line, written as
+    /// disjunctions like `(!a ∨ b ∨ x3)`. Variables are shown by their
+    /// names, or as `x<i>` with their DIMACS number if unnamed.
+    pub fn debug_clauses(&self) -> String {
+        let mut out = String::new();
+        for clause in self.clauses() {
+            let lits: Vec<String> = clause.iter().map(|lit| {
+                let name = match self.names.get(&lit.var) {
+                    Some(name) => name.clone(),
+                    None => format!("x{}", lit.var + 1),
+                };
+                if lit.negated { format!("!{}", name) } else { name }
+            }).collect();
+            out.push_str(&format!("({})\n", lits.join(" ∨ ")));
+        }
+        out
+    }
+    /// Writes the CNF to `path` with `export_dimacs`, variable names
+    /// included.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.export_dimacs(&mut w)?;
+        w.flush()
+    }
+    /// Reads a machine written by `save`.
+    ///
+    /// The loaded machine has the same variables and clauses, and the
+    /// `ndbool`s of the saved one are not valid for it; get handles by
+    /// number with `dimacs_var` or by name with `named_var`. Tagged
+    /// assertions are loaded as plain clauses.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<NdMachine> {
+        NdMachine::import_dimacs(&mut File::open(path)?).map(|(machine, _)| machine)
+    }
+    /// Reads a DIMACS CNF into a new machine, returning it with its
+    /// variables in DIMACS order, so that `vars[i]` is variable `i + 1`.
+    ///
+    /// The `p cnf` header is optional, comments are skipped except for the
+    /// names written by `export_dimacs`, and clauses may span lines. More
+    /// constraints can then be added to the machine as usual.
+    pub fn import_dimacs<R: Read>(r: &mut R) -> io::Result<(NdMachine, Vec<ndbool>)> {
+        let cnf = parse_dimacs(&mut BufReader::new(r))?;
+        let machine = NdMachine::from_cnf(&cnf);
+        let vars = (1..cnf.num_vars + 1).map(|n| machine.dimacs_var(n).unwrap()).collect();
+        Ok((machine, vars))
+    }
+    /// Variable `n` as numbered in `export_dimacs`, counting from 1, or
+    /// `None` if the machine has fewer variables.
+    pub fn dimacs_var(&self, n: usize) -> Option<ndbool> {
+        if (1..=self.num_vars).contains(&n) {
+            Some(self.wrap(Lit { var: n - 1, negated: false }))
+        } else {
+            None
+        }
+    }
+    /// The variable created by `named` with this name, or loaded with it.
+    pub fn named_var(&self, name: &str) -> Option<ndbool> {
+        let var = self.names.iter().filter(|&(_, n)| n == name).map(|(&var, _)| var).min()?;
+        Some(self.wrap(Lit { var, negated: false }))
+    }
+    fn from_cnf(cnf: &Cnf) -> NdMachine {
+        let mut machine = NdMachine::new();
+        let vars: Vec<ndbool> = (0..cnf.num_vars).map(|_| machine.fresh()).collect();
+        for &(var, ref name) in &cnf.names {
+            machine.names.insert(var - 1, name.clone());
+        }
+        for clause in &cnf.clauses {
+            let lits: Vec<Lit> = clause.iter().map(|&l| {
+                let v = vars[l.unsigned_abs() - 1].0;
+                if l < 0 { !v } else { v }
+            }).collect();
+            machine.add_clause(&lits);
+        }
+        machine
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use tests::solver;
+
+    fn export(machine: &NdMachine) -> String {
+        let mut buf = vec![];
+        machine.export_dimacs(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_export_header() {
+        let mut machine = NdMachine::new();
+        let b0 = machine.fresh();
+        let b1 = machine.fresh();
+        let x = machine.and(b0, b1);
+        machine.assert(!x);
+        let text = export(&machine);
+        let mut lines = text.lines();
+        // Two inputs and the gate output; three gate clauses and the assertion.
+        assert_eq!(lines.next(), Some("p cnf 3 4"));
+        let clauses: Vec<_> = lines.collect();
+        assert_eq!(clauses.len(), 4);
+        assert!(clauses.iter().all(|c| c.ends_with(" 0")));
+        assert_eq!(clauses[3], "-3 0");
+    }
+
+    #[test]
+    fn test_parse_dimacs() {
+        let text = "c comment\nc 2 b\np cnf 3 2\n1 -2\n 3 0\n-1 0\nc 7 out of range\n";
+        let cnf = parse_dimacs(&mut Cursor::new(text)).unwrap();
+        assert_eq!(cnf.num_vars, 3);
+        assert_eq!(cnf.clauses, vec![vec![1, -2, 3], vec![-1]]);
+        assert_eq!(cnf.names, vec![(2, "b".to_string())]);
+        assert!(parse_dimacs(&mut Cursor::new("p cnf 1 1\n2 0\n")).is_err());
+        assert!(parse_dimacs(&mut Cursor::new("p cnf 1 1\nx 0\n")).is_err());
+    }
+
+    #[test]
+    fn test_import_dimacs() {
+        let solver = solver();
+        let mut machine = NdMachine::new();
+        let b0 = machine.fresh();
+        let b1 = machine.fresh();
+        let x = machine.xor(b0, b1);
+        machine.assert(x);
+        machine.assert_tagged(b0, "b0");
+        let text = export(&machine);
+        let (mut copy, vars) = NdMachine::import_dimacs(&mut Cursor::new(text.clone())).unwrap();
+        // The inputs, the gate and the selector of the tag.
+        assert_eq!(vars.len(), 4);
+        assert_eq!(export(&copy), text);
+        assert!(copy.solve_by(&solver));
+        assert!(copy.value(vars[0]) && !copy.value(vars[1]));
+        let y = copy.and(vars[0], vars[1]);
+        copy.assert(!y);
+        assert!(copy.solve_by(&solver));
+        copy.assert(vars[1]);
+        assert!(!copy.solve_by(&solver));
+
+        let text = "c from another tool\n1 -2\n  0 2\n0\n";
+        let (mut imported, vars) = NdMachine::import_dimacs(&mut Cursor::new(text)).unwrap();
+        assert_eq!(vars.len(), 2);
+        assert!(imported.solve_by(&solver));
+        assert!(imported.value(vars[0]) && imported.value(vars[1]));
+        assert!(NdMachine::import_dimacs(&mut Cursor::new("p cnf 1 1\n-2 0\n")).is_err());
+    }
+
+    #[test]
+    fn test_save_load() {
+        let solver = solver();
+        let mut machine = NdMachine::new();
+        let a = machine.named("a");
+        let b = machine.named("b");
+        let x = machine.and(a, !b);
+        machine.assert(x);
+        let file = NamedTempFile::new().unwrap();
+        machine.save(file.path()).unwrap();
+        let mut loaded = NdMachine::load(file.path()).unwrap();
+        assert_eq!(loaded.stats(), machine.stats());
+        let (la, lb) = (loaded.named_var("a").unwrap(), loaded.named_var("b").unwrap());
+        assert_eq!(loaded.name(la), Some("a"));
+        assert!(loaded.named_var("x").is_none());
+        assert!(loaded.dimacs_var(3).is_some() && loaded.dimacs_var(4).is_none());
+        assert!(loaded.solve_by(&solver));
+        assert!(loaded.value(la) && !loaded.value(lb));
+        loaded.assert(lb);
+        assert!(!loaded.solve_by(&solver));
+
+        let y = machine.or(!a, b);
+        machine.assert_tagged(y, "contradiction");
+        machine.save(file.path()).unwrap();
+        let mut loaded = NdMachine::load(file.path()).unwrap();
+        assert_eq!(loaded.stats(), machine.stats());
+        assert!(!loaded.solve_by(&solver));
+    }
+
+    #[test]
+    fn test_debug_clauses() {
+        let mut machine = NdMachine::new();
+        let a = machine.named("a");
+        let b = machine.named("b");
+        let x = machine.and(a, b);
+        machine.assert(x);
+        assert_eq!(machine.debug_clauses(), "(!a ∨ !b ∨ x3)\n(a ∨ !x3)\n(b ∨ !x3)\n(x3)\n");
+        machine.assert_tagged(!a, "not a");
+        assert!(machine.debug_clauses().ends_with("(x3)\n(!x4 ∨ !a)\n(x4)\n"));
+    }
+
+    #[test]
+    fn test_export_wcnf() {
+        let mut machine = NdMachine::new();
+        let b0 = machine.fresh();
+        let b1 = machine.fresh();
+        let either = machine.or(!b0, !b1);
+        machine.assert(either);
+        machine.assert_soft(b0, 2);
+        machine.assert_soft(b1, 5);
+        let mut buf = vec![];
+        machine.export_wcnf(&mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines[0], "p wcnf 3 6 8");
+        // Three clauses of the gate and the assertion.
+        assert!(lines[1..5].iter().all(|l| l.starts_with("8 ")));
+        assert_eq!(&lines[5..], ["2 1 0", "5 2 0"]);
+    }
+
+    #[test]
+    fn test_export_names() {
+        let mut machine = NdMachine::new();
+        let _ = machine.fresh();
+        let b = machine.named("queen_at_b3");
+        assert_eq!(machine.name(b), Some("queen_at_b3"));
+        machine.assert(b);
+        assert_eq!(export(&machine), "c 2 queen_at_b3\np cnf 2 1\n2 0\n");
+    }
+
+    #[test]
+    fn test_export_round_trip() {
+        let mut machine = NdMachine::new();
+        let b0 = machine.fresh();
+        let b1 = machine.fresh();
+        let x = machine.xor(b0, b1);
+        machine.assert(x);
+        machine.assert_tagged(b0, "b0");
+        let text = export(&machine);
+
+        // Rebuild the exported clauses on a new machine.
+        let mut copy = NdMachine::new();
+        let header: Vec<usize> = text.lines().next().unwrap().split_whitespace().skip(2).map(|n| n.parse().unwrap()).collect();
+        let vars: Vec<_> = (0..header[0]).map(|_| copy.fresh()).collect();
+        for line in text.lines().skip(1) {
+            let lits: Vec<_> = line.split_whitespace().map(|t| t.parse::<isize>().unwrap()).take_while(|&l| l != 0).collect();
+            let clause = lits.iter().fold(copy.f(), |acc, &l| {
+                let v = vars[l.unsigned_abs() - 1];
+                copy.or(acc, if l < 0 { !v } else { v })
+            });
+            copy.assert(clause);
+        }
+        assert_eq!(header[1], text.lines().count() - 1);
+        assert!(copy.solve_by(&solver()));
+        assert!(copy.value(vars[0]));
+        assert!(!copy.value(vars[1]));
+        copy.assert(vars[1]);
+        assert!(!copy.solve_by(&solver()));
+    }
+}
@@ -0,0 +1,108 @@
+//! Graphviz output of the gate structure.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use {Gate, NdMachine};
+
+impl NdMachine {
+    /// The gates built so far as a Graphviz DOT digraph.
+    ///
+    /// Each gate is a node labeled with its operation, with an edge to each
+    /// of its inputs; inputs used negated have dashed edges, and those of
+    /// `select` are labeled `if`, `then` and `else`. Other variables appear
+    /// as leaves labeled with their names, or as `x<i>` with their DIMACS
+    /// number if unnamed. Operators such as `!`, `^` and `nand` that negate
+    /// a gate add no node of their own. Constraints are not shown.
+    pub fn to_dot(&self) -> String {
+        let mut gates: Vec<_> = self.gates.iter().map(|(&(kind, ref inputs), &out)| (out.var, kind, inputs)).collect();
+        gates.sort_by_key(|&(var, _, _)| var);
+        let outputs: BTreeSet<usize> = gates.iter().map(|&(var, _, _)| var).collect();
+        let leaves: BTreeSet<usize> = gates.iter()
+            .flat_map(|&(_, _, inputs)| inputs.iter().map(|l| l.var))
+            .filter(|var| !outputs.contains(var))
+            .collect();
+
+        let mut out = String::from("digraph {\n");
+        for &var in &leaves {
+            let label = if self.t_lit.map(|l| l.var) == Some(var) {
+                "true".to_string()
+            } else if self.f_lit.map(|l| l.var) == Some(var) {
+                "false".to_string()
+            } else {
+                match self.names.get(&var) {
+                    Some(name) => name.clone(),
+                    None => format!("x{}", var + 1),
+                }
+            };
+            writeln!(out, "  x{} [label={:?}, shape=box];", var + 1, label).unwrap();
+        }
+        for &(var, kind, _) in &gates {
+            let label = match kind {
+                Gate::And => "and",
+                Gate::Or => "or",
+                Gate::Xnor => "xnor",
+                Gate::Select => "select",
+            };
+            writeln!(out, "  x{} [label={:?}];", var + 1, label).unwrap();
+        }
+        for &(var, kind, inputs) in &gates {
+            for (i, input) in inputs.iter().enumerate() {
+                let mut attrs = vec![];
+                if input.negated {
+                    attrs.push("style=dashed".to_string());
+                }
+                if kind == Gate::Select {
+                    attrs.push(format!("label={:?}", ["if", "then", "else"][i]));
+                }
+                let attrs = if attrs.is_empty() { String::new() } else { format!(" [{}]", attrs.join(", ")) };
+                writeln!(out, "  x{} -> x{}{};", var + 1, input.var + 1, attrs).unwrap();
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use NdMachine;
+
+    #[test]
+    fn test_to_dot() {
+        let mut machine = NdMachine::new();
+        let a = machine.named("a");
+        let b = machine.named("b");
+        let c = machine.named("c");
+        let x = machine.and(a, b);
+        machine.or(x, c);
+        let dot = machine.to_dot();
+        assert_eq!(dot.matches(" [label=").count(), 5);
+        assert_eq!(dot.matches(" -> ").count(), 4);
+        assert_eq!(dot, "digraph {\n  \
+            x1 [label=\"a\", shape=box];\n  \
+            x2 [label=\"b\", shape=box];\n  \
+            x3 [label=\"c\", shape=box];\n  \
+            x4 [label=\"and\"];\n  \
+            x5 [label=\"or\"];\n  \
+            x4 -> x1;\n  \
+            x4 -> x2;\n  \
+            x5 -> x3;\n  \
+            x5 -> x4;\n\
+            }\n");
+    }
+
+    #[test]
+    fn test_to_dot_negated_select() {
+        let mut machine = NdMachine::new();
+        let a = machine.fresh();
+        let b = machine.fresh();
+        let t = machine.t();
+        machine.select(!a, b, t);
+        let dot = machine.to_dot();
+        assert!(dot.contains("x4 -> x1 [style=dashed, label=\"if\"];\n"));
+        assert!(dot.contains("x4 -> x2 [label=\"then\"];\n"));
+        assert!(dot.contains("x3 [label=\"true\", shape=box];\n"));
+        assert_eq!(NdMachine::new().to_dot(), "digraph {\n}\n");
+    }
+}
@@ -0,0 +1,213 @@
+//! Choice between the full Tseitin encoding of gates and the
+//! polarity-aware Plaisted–Greenbaum encoding.
+
+use std::collections::HashMap;
+
+use {Gate, Lit, Model, NdMachine};
+
+/// How the clauses of gates such as `a & b` are added to the instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    /// Every gate is defined in both directions as soon as it is built.
+    #[default]
+    Tseitin,
+    /// Gate clauses are held back until the gate is used in a constraint,
+    /// and only the direction needed by that use is added. A gate used in
+    /// both polarities ends up fully encoded.
+    PlaistedGreenbaum,
+}
+
+/// How the comparisons of `ndu` and `ndi`, such as `ndlt`, are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Comparator {
+    /// A chain of one XOR and one `select` per bit, from the least
+    /// significant bit up, so that the most significant differing bit
+    /// decides: 8 clauses per bit.
+    #[default]
+    Chain,
+    /// The borrow out of a ripple-carry subtraction: 17 clauses per bit.
+    Borrow,
+}
+
+/// Sets the comparator of the thread-local machine. See
+/// `NdMachine::set_comparator`.
+pub fn set_comparator(comparator: Comparator) {
+    NdMachine::with(|machine| machine.set_comparator(comparator))
+}
+
+/// Sets the encoding of the thread-local machine. See
+/// `NdMachine::set_encoding`.
+pub fn set_encoding(encoding: Encoding) {
+    NdMachine::with(|machine| machine.set_encoding(encoding))
+}
+
+/// The definition of a gate whose clauses have not all been added yet.
+#[derive(Clone)]
+pub(crate) struct GateDef {
+    pub kind: Gate,
+    pub inputs: Vec<Lit>,
+    pub clauses: Vec<Vec<Lit>>,
+}
+
+impl Gate {
+    fn eval(self, inputs: &[bool]) -> bool {
+        match self {
+            Gate::And => inputs.iter().all(|&b| b),
+            Gate::Or => inputs.iter().any(|&b| b),
+            Gate::Xnor => inputs[0] == inputs[1],
+            Gate::Select => if inputs[0] { inputs[1] } else { inputs[2] },
+        }
+    }
+}
+
+impl NdMachine {
+    /// Selects the encoding of gates built from now on; the default is
+    /// `Encoding::Tseitin`.
+    ///
+    /// Under `Encoding::PlaistedGreenbaum` the instance stays
+    /// equisatisfiable, but the solver's value of a gate output that is
+    /// not fully encoded may differ from the gate's function. `value`
+    /// therefore recomputes such gates from their inputs.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+    /// Selects the encoding of comparisons built from now on; the default
+    /// is the smaller `Comparator::Chain`.
+    pub fn set_comparator(&mut self, comparator: Comparator) {
+        self.comparator = comparator;
+    }
+    /// Adds the held-back clauses needed for `lit` to imply its definition,
+    /// and, transitively, those of the gates they mention.
+    pub(crate) fn require(&mut self, lit: Lit) {
+        let mut stack = vec![lit];
+        while let Some(lit) = stack.pop() {
+            if !self.defs.contains_key(&lit.var) || !self.required.insert(lit) {
+                continue;
+            }
+            let clauses: Vec<Vec<Lit>> = self.defs[&lit.var].clauses.iter()
+                .filter(|c| c.contains(&!lit))
+                .cloned()
+                .collect();
+            for clause in clauses {
+                stack.extend(clause.iter().cloned().filter(|&l| l != !lit));
+                self.clauses.push(clause);
+            }
+        }
+    }
+    /// Reads `lit` from `model`, evaluating gates that are not fully
+    /// encoded from their inputs.
+    pub(crate) fn eval(&self, model: &Model, lit: Lit) -> Option<bool> {
+        let value = model.get(lit)?;
+        if self.defs.is_empty() {
+            return Some(value);
+        }
+        let mut memo: HashMap<usize, bool> = HashMap::new();
+        let mut stack = vec![lit.var];
+        while let Some(&var) = stack.last() {
+            if memo.contains_key(&var) {
+                stack.pop();
+                continue;
+            }
+            let pos = Lit { var, negated: false };
+            match self.defs.get(&var) {
+                Some(def) if !(self.required.contains(&pos) && self.required.contains(&!pos)) => {
+                    let pending: Vec<usize> = def.inputs.iter().map(|l| l.var).filter(|v| !memo.contains_key(v)).collect();
+                    if pending.is_empty() {
+                        let inputs: Vec<bool> = def.inputs.iter().map(|l| memo[&l.var] != l.negated).collect();
+                        memo.insert(var, def.kind.eval(&inputs));
+                        stack.pop();
+                    } else {
+                        stack.extend(pending);
+                    }
+                }
+                _ => {
+                    memo.insert(var, model.get(pos)?);
+                    stack.pop();
+                }
+            }
+        }
+        Some(memo[&lit.var] != lit.negated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tests::solve;
+    use {init, ndassert, ndassert_ne, ndbool, stats};
+
+    fn init_pg() {
+        init();
+        set_encoding(Encoding::PlaistedGreenbaum);
+    }
+
+    #[test]
+    fn test_pg_comm_assoc() {
+        init_pg();
+        let b0 = ndbool::fresh();
+        let b1 = ndbool::fresh();
+        let b2 = ndbool::fresh();
+        ndassert_ne((b0 & b1) & b2, b0 & (b1 & b2));
+        assert!(!solve());
+
+        init_pg();
+        let b0 = ndbool::fresh();
+        let b1 = ndbool::fresh();
+        let b2 = ndbool::fresh();
+        ndassert_ne((b0 | b1) | b2, b0 | (b1 | b2));
+        assert!(!solve());
+
+        init_pg();
+        let b0 = ndbool::fresh();
+        let b1 = ndbool::fresh();
+        ndassert_ne(b0 ^ b1, b1 ^ b0);
+        assert!(!solve());
+    }
+
+    #[test]
+    fn test_pg_single_polarity() {
+        init_pg();
+        let b0 = ndbool::fresh();
+        let b1 = ndbool::fresh();
+        let b2 = ndbool::fresh();
+        let x = (b0 | b1) & b2;
+        assert_eq!(stats().num_clauses, 0);
+        ndassert(x);
+        // x => (b0 | b1) and x => b2, then (b0 | b1) => b0 | b1, plus the
+        // assertion: four clauses instead of seven.
+        assert_eq!(stats().num_clauses, 4);
+        assert!(solve());
+        assert!(x.value());
+        assert!(b2.value());
+        assert!(b0.value() || b1.value());
+    }
+
+    #[test]
+    fn test_pg_values() {
+        init_pg();
+        let b0 = ndbool::fresh();
+        let b1 = ndbool::fresh();
+        let x = b0 & b1;
+        let y = ndbool::select(b0, b1, !b1);
+        ndassert(b0);
+        ndassert(!b1);
+        assert!(solve());
+        // Neither gate is constrained, but their values follow the inputs.
+        assert!(!x.value());
+        assert!(!y.value());
+    }
+
+    #[test]
+    fn test_pg_after_tseitin() {
+        init();
+        let b0 = ndbool::fresh();
+        let b1 = ndbool::fresh();
+        set_encoding(Encoding::PlaistedGreenbaum);
+        let x = b0 ^ b1;
+        set_encoding(Encoding::Tseitin);
+        let y = x | b0;
+        ndassert(!y);
+        ndassert(b1);
+        assert!(!solve());
+    }
+}
@@ -0,0 +1,512 @@
+//! An external DIMACS / MiniSAT solver that reports why it failed.
+
+use std::cell::RefCell;
+use std::env;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use sat::{Assignment, Instance};
+use sat::solver::Solver;
+use tempfile::{self, NamedTempFile};
+
+use dimacs::dimacs_format;
+use portfolio::cancel_flag;
+use SolverError;
+
+/// Invokes an external program using the DIMACS / MiniSAT file formats,
+/// like `sat::solver::Dimacs`.
+///
+/// Instead of panicking with an opaque `unwrap` message, a solver that
+/// cannot be spawned or exits without an answer makes `solve_by_result`
+/// return `SolveResult::Unknown`; the reason, including the solver's
+/// stderr, is then available from `NdMachine::last_error`.
+///
+/// ```ignore
+/// let solver = ExternalSolver::new(|| Command::new("minisat"));
+/// if ndmachine::solve_by_result(&solver) == SolveResult::Unknown {
+///     eprintln!("{}", ndmachine::last_solve_error().unwrap());
+/// }
+/// ```
+pub struct ExternalSolver<F> {
+    cmd_factory: F,
+    keep_cnf: bool,
+    timeout: Option<Duration>,
+    proof_flag: Option<OsString>,
+    last_cnf: RefCell<Option<PathBuf>>,
+    last_stats: RefCell<SolveStats>,
+    last_proof: RefCell<Option<PathBuf>>,
+}
+
+/// Statistics reported by an external solver on its standard output.
+///
+/// They are read from lines of the form `<name> : <number> ...`, optionally
+/// prefixed by `c ` as DIMACS comments. MiniSat and Glucose report all of
+/// these; CaDiCaL and Kissat report conflicts and decisions, with their
+/// other figures under different names. Statistics a solver does not report
+/// stay `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SolveStats {
+    pub conflicts: Option<u64>,
+    pub decisions: Option<u64>,
+    pub propagations: Option<u64>,
+    pub restarts: Option<u64>,
+    /// CPU time in seconds.
+    pub cpu_time: Option<f64>,
+}
+
+impl SolveStats {
+    /// Parses the statistics lines of a solver's output, ignoring the rest.
+    pub fn parse(output: &str) -> SolveStats {
+        let mut stats = SolveStats::default();
+        for line in output.lines() {
+            let line = line.trim_start_matches("c ").trim();
+            let (key, rest) = match line.find(':') {
+                Some(i) => (line[..i].trim().to_lowercase(), &line[i + 1..]),
+                None => continue,
+            };
+            let number = match rest.split_whitespace().next() {
+                Some(n) => n,
+                None => continue,
+            };
+            match &key[..] {
+                "conflicts" => stats.conflicts = number.parse().ok(),
+                "decisions" => stats.decisions = number.parse().ok(),
+                "propagations" => stats.propagations = number.parse().ok(),
+                "restarts" => stats.restarts = number.parse().ok(),
+                "cpu time" | "total process time since initialization" => stats.cpu_time = number.parse().ok(),
+                _ => {}
+            }
+        }
+        stats
+    }
+}
+
+impl<F: Fn() -> Command> ExternalSolver<F> {
+    /// The input and output filenames are appended to the command as
+    /// additional arguments.
+    pub fn new(cmd_factory: F) -> Self {
+        ExternalSolver {
+            cmd_factory,
+            keep_cnf: false,
+            timeout: None,
+            proof_flag: None,
+            last_cnf: RefCell::new(None),
+            last_stats: RefCell::new(SolveStats::default()),
+            last_proof: RefCell::new(None),
+        }
+    }
+    /// The CNF file of the last run, if `DimacsBuilder::keep_cnf` was set.
+    pub fn last_cnf(&self) -> Option<PathBuf> {
+        self.last_cnf.borrow().clone()
+    }
+    /// The statistics printed by the solver in its last run.
+    pub fn last_stats(&self) -> SolveStats {
+        self.last_stats.borrow().clone()
+    }
+    /// The DRAT proof written by the last run, if `DimacsBuilder::drat_proof`
+    /// was set and the run answered UNSAT with a non-empty proof. The file
+    /// is kept for an external checker such as drat-trim.
+    pub fn last_proof(&self) -> Option<PathBuf> {
+        self.last_proof.borrow().clone()
+    }
+    fn run(&self, instance: &Instance) -> Result<Option<Assignment>, String> {
+        let format = dimacs_format();
+        let tmp_err = |e: io::Error| format!("cannot create temporary file: {}", e);
+        let mut in_file = tempfile::NamedTempFileOptions::new().suffix(".cnf").create().map_err(tmp_err)?;
+        let out_file = NamedTempFile::new().map_err(tmp_err)?;
+        let mut dimacs = vec![];
+        format.write_instance(&mut dimacs, instance);
+        in_file.write_all(&dimacs).map_err(tmp_err)?;
+        // The header reads `p cnf <vars> <clauses>`.
+        let num_vars = String::from_utf8_lossy(&dimacs).split_whitespace().nth(2).and_then(|n| n.parse().ok()).unwrap_or(0);
+
+        let mut cmd = (self.cmd_factory)();
+        let program = cmd.get_program().to_string_lossy().into_owned();
+        *self.last_proof.borrow_mut() = None;
+        let proof_file = match self.proof_flag {
+            Some(ref flag) => {
+                let file = tempfile::NamedTempFileOptions::new().suffix(".drat").create().map_err(tmp_err)?;
+                let mut arg = flag.clone();
+                arg.push(file.path());
+                cmd.arg(arg);
+                Some(file)
+            }
+            None => None,
+        };
+        cmd.arg(in_file.path()).arg(out_file.path());
+        cmd.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+        *self.last_stats.borrow_mut() = SolveStats::default();
+        let cancel = cancel_flag();
+        let output = match (self.timeout, cancel) {
+            (None, None) => cmd.output(),
+            (timeout, cancel) => output_with_timeout(&mut cmd, timeout, cancel.as_deref()),
+        };
+        let output = output.map_err(|e| format!("cannot run solver `{}`: {}", program, e))?;
+        *self.last_stats.borrow_mut() = SolveStats::parse(&String::from_utf8_lossy(&output.stdout));
+        if self.keep_cnf {
+            let path = in_file.path().to_path_buf();
+            in_file.persist(&path).map_err(|e| format!("cannot keep {}: {}", path.display(), e.error))?;
+            *self.last_cnf.borrow_mut() = Some(path);
+        }
+
+        let mut reader = BufReader::new(File::open(out_file.path()).map_err(tmp_err)?);
+        let mut head = String::new();
+        let _ = reader.read_line(&mut head);
+        if head != "SAT\n" && head != "UNSAT\n" {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let mut message = format!("solver `{}` exited with {} without an answer", program, output.status);
+            if !stderr.trim().is_empty() {
+                message.push_str(": ");
+                message.push_str(stderr.trim());
+            }
+            return Err(message);
+        }
+        if let Some(file) = proof_file {
+            let written = file.metadata().map(|m| m.len() > 0).unwrap_or(false);
+            if head == "UNSAT\n" && written {
+                let path = file.path().to_path_buf();
+                file.persist(&path).map_err(|e| format!("cannot keep {}: {}", path.display(), e.error))?;
+                *self.last_proof.borrow_mut() = Some(path);
+            }
+        }
+        let mut text = head;
+        reader.read_to_string(&mut text).map_err(|e| format!("cannot read solver output: {}", e))?;
+        Ok(format.read_solution(&mut io::Cursor::new(text), num_vars))
+    }
+}
+
+/// Like `Command::output`, but kills the process once `timeout` has
+/// passed or `cancel` is set; the killed process is reaped before
+/// returning `TimedOut` or `Interrupted`.
+fn output_with_timeout(cmd: &mut Command, timeout: Option<Duration>, cancel: Option<&AtomicBool>) -> io::Result<Output> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let mut child = cmd.spawn()?;
+    // Drain the pipes concurrently so that a chatty solver cannot block on
+    // a full pipe.
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+    loop {
+        if let Some(status) = child.try_wait()? {
+            let stdout = stdout.join().unwrap_or_default();
+            let stderr = stderr.join().unwrap_or_default();
+            return Ok(Output { status, stdout, stderr });
+        }
+        if let (Some(deadline), Some(timeout)) = (deadline, timeout) {
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                child.wait()?;
+                return Err(io::Error::new(io::ErrorKind::TimedOut, format!("timed out after {:?}", timeout)));
+            }
+        }
+        if cancel.is_some_and(|cancel| cancel.load(Ordering::SeqCst)) {
+            let _ = child.kill();
+            child.wait()?;
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled"));
+        }
+        let poll = Duration::from_millis(10);
+        thread::sleep(deadline.map_or(poll, |deadline| poll.min(deadline.saturating_duration_since(Instant::now()))));
+    }
+}
+
+fn drain<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = vec![];
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+/// Configures an `ExternalSolver` for a DIMACS / MiniSAT-style program.
+///
+/// ```ignore
+/// let solver = DimacsBuilder::new("/opt/glucose/glucose")
+///     .args(&["-model", "-verb=0"])
+///     .current_dir("/tmp")
+///     .build()?;
+/// ndmachine::solve_by(&solver);
+/// ```
+pub struct DimacsBuilder {
+    program: PathBuf,
+    args: Vec<OsString>,
+    current_dir: Option<PathBuf>,
+    keep_cnf: bool,
+    timeout: Option<Duration>,
+    proof_flag: Option<OsString>,
+}
+
+impl DimacsBuilder {
+    /// A bare name such as `"minisat"` is looked up in `PATH`.
+    pub fn new<P: AsRef<Path>>(program: P) -> Self {
+        DimacsBuilder {
+            program: program.as_ref().to_path_buf(),
+            args: vec![],
+            current_dir: None,
+            keep_cnf: false,
+            timeout: None,
+            proof_flag: None,
+        }
+    }
+    /// Adds a flag, passed before the input and output filenames.
+    pub fn arg<S: Into<OsString>>(mut self, arg: S) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+    pub fn args<S: AsRef<str>>(mut self, args: &[S]) -> Self {
+        self.args.extend(args.iter().map(|a| OsString::from(a.as_ref())));
+        self
+    }
+    /// The working directory of the solver process.
+    pub fn current_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.current_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+    /// Keeps the temporary CNF file of each run instead of deleting it. Its
+    /// path is reported by `ExternalSolver::last_cnf`.
+    pub fn keep_cnf(mut self, keep: bool) -> Self {
+        self.keep_cnf = keep;
+        self
+    }
+    /// Kills the solver after `timeout`; the solve then returns
+    /// `SolveResult::Unknown`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+    /// Asks for a DRAT proof of UNSAT answers, passed to the solver as
+    /// `flag` immediately followed by the path of a fresh proof file. The
+    /// proof is reported by `ExternalSolver::last_proof`.
+    ///
+    /// MiniSat 2.2 cannot write proofs. Glucose does with
+    /// `.arg("-certified").drat_proof("-certified-output=")`, as do
+    /// MiniSat-based solvers such as MapleSAT with `-drup-file=`. A solver
+    /// that ignores the flag leaves the file empty, and then there is no
+    /// proof but the answer is returned as usual.
+    pub fn drat_proof<S: Into<OsString>>(mut self, flag: S) -> Self {
+        self.proof_flag = Some(flag.into());
+        self
+    }
+    /// Builds the solver, failing with `NotFound` if the program does not
+    /// exist.
+    pub fn build(self) -> io::Result<ExternalSolver<impl Fn() -> Command>> {
+        let program = find_program(&self.program).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("solver `{}` not found", self.program.display()))
+        })?;
+        let (args, current_dir) = (self.args, self.current_dir);
+        let mut solver = ExternalSolver::new(move || {
+            let mut cmd = Command::new(&program);
+            cmd.args(&args);
+            if let Some(ref dir) = current_dir {
+                cmd.current_dir(dir);
+            }
+            cmd
+        });
+        solver.keep_cnf = self.keep_cnf;
+        solver.timeout = self.timeout;
+        solver.proof_flag = self.proof_flag;
+        Ok(solver)
+    }
+}
+
+/// Resolves `program` like a shell would: paths are checked directly and
+/// bare names are searched in `PATH`.
+fn find_program(program: &Path) -> Option<PathBuf> {
+    if program.components().count() > 1 {
+        return if program.is_file() { Some(program.to_path_buf()) } else { None };
+    }
+    env::var_os("PATH").and_then(|paths| {
+        env::split_paths(&paths).map(|dir| dir.join(program)).find(|p| p.is_file())
+    })
+}
+
+impl<F: Fn() -> Command> Solver for ExternalSolver<F> {
+    fn solve(&self, instance: &Instance) -> Option<Assignment> {
+        match self.run(instance) {
+            Ok(result) => result,
+            Err(message) => SolverError::raise(message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {init, last_solve_error, ndassert, ndbool, solve_by_result, NdMachine, SolveResult};
+
+    #[test]
+    fn test_missing_binary() {
+        init();
+        ndassert(ndbool::fresh());
+        let solver = ExternalSolver::new(|| Command::new("ndmachine-no-such-solver"));
+        assert_eq!(solve_by_result(&solver), SolveResult::Unknown);
+        let error = last_solve_error().unwrap();
+        assert!(error.contains("ndmachine-no-such-solver"), "{}", error);
+    }
+
+    #[test]
+    fn test_stderr_is_reported() {
+        init();
+        ndassert(ndbool::fresh());
+        let solver = ExternalSolver::new(|| {
+            let mut cmd = Command::new("sh");
+            cmd.arg("-c").arg("echo out of licences >&2; exit 3").arg("sh");
+            cmd
+        });
+        assert_eq!(solve_by_result(&solver), SolveResult::Unknown);
+        let error = last_solve_error().unwrap();
+        assert!(error.contains("out of licences"), "{}", error);
+    }
+
+    #[test]
+    fn test_builder() {
+        assert_eq!(DimacsBuilder::new("ndmachine-no-such-solver").build().err().unwrap().kind(), io::ErrorKind::NotFound);
+        assert!(DimacsBuilder::new("/nonexistent/minisat").build().is_err());
+
+        let solver = DimacsBuilder::new("sh")
+            .args(&["-c", "pwd >&2; exit 1", "sh"])
+            .current_dir("/")
+            .build()
+            .unwrap();
+        init();
+        ndassert(ndbool::fresh());
+        assert_eq!(solve_by_result(&solver), SolveResult::Unknown);
+        assert!(last_solve_error().unwrap().ends_with(": /"));
+        assert_eq!(solver.last_cnf(), None);
+    }
+
+    #[test]
+    fn test_builder_keep_cnf() {
+        let solver = DimacsBuilder::new("minisat").keep_cnf(true).build().unwrap();
+        let mut machine = NdMachine::new();
+        let b0 = machine.fresh();
+        machine.assert(!b0);
+        assert_eq!(machine.solve_by_result(&solver), SolveResult::Sat);
+        let path = solver.last_cnf().unwrap();
+        let mut text = String::new();
+        File::open(&path).unwrap().read_to_string(&mut text).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(text, "p cnf 1 1\n-1 0\n");
+    }
+
+    #[test]
+    fn test_drat_proof() {
+        // Writes `proof` to the file named by `--proof=<path>` and answers
+        // UNSAT.
+        let fake = |proof: &str| {
+            let script = format!(r#"printf '{}' > "${{1#--proof=}}"; echo UNSAT > "$3""#, proof);
+            DimacsBuilder::new("sh").args(&["-c", &script, "sh"]).drat_proof("--proof=").build().unwrap()
+        };
+        let mut machine = NdMachine::new();
+        let b0 = machine.fresh();
+        machine.assert(b0);
+        let solver = fake("0\\n");
+        assert_eq!(machine.solve_by_result(&solver), SolveResult::Unsat);
+        let path = solver.last_proof().unwrap();
+        let mut text = String::new();
+        File::open(&path).unwrap().read_to_string(&mut text).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(text, "0\n");
+
+        // An empty proof file means the solver does not support proofs.
+        let solver = fake("");
+        assert_eq!(machine.solve_by_result(&solver), SolveResult::Unsat);
+        assert_eq!(solver.last_proof(), None);
+
+        let solver = DimacsBuilder::new("minisat").build().unwrap();
+        assert_eq!(machine.solve_by_result(&solver), SolveResult::Sat);
+        assert_eq!(solver.last_proof(), None);
+    }
+
+    #[test]
+    fn test_timeout() {
+        let solver = DimacsBuilder::new("sh")
+            .args(&["-c", "sleep 10", "sh"])
+            .timeout(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        init();
+        ndassert(ndbool::fresh());
+        let start = Instant::now();
+        assert_eq!(solve_by_result(&solver), SolveResult::Unknown);
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(last_solve_error().unwrap().contains("timed out"));
+    }
+
+    /// The pigeonhole principle for 13 pigeons in 12 holes, which takes
+    /// resolution-based solvers far longer than a second.
+    #[cfg(feature = "slow-tests")]
+    #[test]
+    fn test_timeout_hard_instance() {
+        use {assert_at_most_k, ndassert};
+
+        init();
+        let holes = 12;
+        let pigeons: Vec<Vec<ndbool>> = (0..holes + 1).map(|_| (0..holes).map(|_| ndbool::fresh()).collect()).collect();
+        for p in &pigeons {
+            ndassert(ndbool::any(p));
+        }
+        for h in 0..holes {
+            let column: Vec<ndbool> = pigeons.iter().map(|p| p[h]).collect();
+            assert_at_most_k(&column, 1);
+        }
+        let solver = DimacsBuilder::new("minisat").timeout(Duration::from_secs(1)).build().unwrap();
+        let start = Instant::now();
+        assert_eq!(solve_by_result(&solver), SolveResult::Unknown);
+        assert!(start.elapsed() < Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_parse_stats() {
+        let minisat = "restarts              : 3\nconflicts             : 215            (21500 /sec)\n\
+                       decisions             : 451            (0.00 % random) (45100 /sec)\n\
+                       propagations          : 3021           (302100 /sec)\nCPU time              : 0.01 s\n";
+        assert_eq!(SolveStats::parse(minisat), SolveStats {
+            conflicts: Some(215),
+            decisions: Some(451),
+            propagations: Some(3021),
+            restarts: Some(3),
+            cpu_time: Some(0.01),
+        });
+        let glucose = "c conflicts             : 17 (1700 /sec)\nc nb reduced Clauses  : 0\ns SATISFIABLE\n";
+        assert_eq!(SolveStats::parse(glucose).conflicts, Some(17));
+        assert_eq!(SolveStats::parse(glucose).decisions, None);
+        assert_eq!(SolveStats::parse("SATISFIABLE\n"), SolveStats::default());
+    }
+
+    #[test]
+    fn test_stats_from_stdout() {
+        let solver = DimacsBuilder::new("sh")
+            .args(&["-c", "echo 'conflicts : 42'; echo UNSAT > \"$2\"", "sh"])
+            .build()
+            .unwrap();
+        let mut machine = NdMachine::new();
+        let b0 = machine.fresh();
+        machine.assert(b0);
+        assert_eq!(machine.solve_by_result(&solver), SolveResult::Unsat);
+        assert_eq!(solver.last_stats().conflicts, Some(42));
+    }
+
+    #[test]
+    fn test_external_solver() {
+        let mut machine = NdMachine::new();
+        let b0 = machine.fresh();
+        let b1 = machine.fresh();
+        let x = machine.xor(b0, b1);
+        machine.assert(x);
+        machine.assert(b0);
+        let solver = ExternalSolver::new(|| Command::new("minisat"));
+        assert_eq!(machine.solve_by_result(&solver), SolveResult::Sat);
+        assert_eq!(machine.last_error(), None);
+        assert!(machine.value(b0));
+        assert!(!machine.value(b1));
+        machine.assert(b1);
+        assert_eq!(machine.solve_by_result(&solver), SolveResult::Unsat);
+    }
+}
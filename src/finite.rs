@@ -0,0 +1,257 @@
+//! Finite-domain variables.
+
+use std::ops::{Add, Mul};
+
+use {assert_exactly_one, ndassert, ndbool, ndu, NdEq, NdMachine};
+
+/// A variable ranging over the cases `0..n`, stored as one-hot bits.
+///
+/// The number of cases is a runtime value given to `fresh` rather than a
+/// type parameter, so that it can depend on the input, like the number of
+/// colors of a coloring problem. Mixing enums of different sizes is
+/// therefore not a type error: they simply never compare equal.
+#[allow(non_camel_case_types)]
+#[derive(Clone)]
+pub struct ndenum {
+    cases: Vec<ndbool>,
+}
+
+impl ndenum {
+    /// Creates a variable over `n` cases, asserting that exactly one of
+    /// them holds.
+    pub fn fresh(n: usize) -> Self {
+        let cases: Vec<ndbool> = (0..n).map(|_| ndbool::fresh()).collect();
+        assert_exactly_one(&cases);
+        ndenum { cases }
+    }
+    pub fn num_cases(&self) -> usize {
+        self.cases.len()
+    }
+    /// True exactly when the variable takes the case `case`.
+    pub fn is(&self, case: usize) -> ndbool {
+        self.cases[case]
+    }
+    pub fn value(&self) -> usize {
+        self.cases.iter().position(|b| b.value()).expect("no case of an ndenum holds")
+    }
+}
+
+/// Compares the one-hot bits; enums with different numbers of cases are
+/// never equal.
+impl NdEq for ndenum {
+    fn ndeq(&self, rhs: &ndenum) -> ndbool {
+        if self.num_cases() != rhs.num_cases() {
+            return ndbool::f();
+        }
+        self.cases.ndeq(&rhs.cases)
+    }
+}
+
+/// A variable ranging over `0..n`, stored in binary as an `ndu` of width
+/// `ceil(log2(n))` restricted to the domain. It needs far fewer variables
+/// than `ndenum` for large domains.
+#[allow(non_camel_case_types)]
+#[derive(Clone)]
+pub struct nddomain {
+    bits: ndu,
+    n: u64,
+}
+
+impl nddomain {
+    /// Creates a variable over `0..n`, asserting that it stays below `n`.
+    ///
+    /// Panics if `n` is zero.
+    pub fn fresh(n: u64) -> Self {
+        assert!(n > 0, "empty domain");
+        let bits = ndu::fresh(64 - (n - 1).leading_zeros() as usize);
+        ndassert(bits.le_const(n - 1));
+        nddomain { bits, n }
+    }
+    /// The constant `value` in the domain `0..n`.
+    ///
+    /// Panics if `value` is not below `n`.
+    pub fn constant(value: u64, n: u64) -> Self {
+        assert!(value < n, "{} outside the domain 0..{}", value, n);
+        nddomain { bits: ndu::constant(value, 64 - (n - 1).leading_zeros() as usize), n }
+    }
+    /// The size `n` of the domain.
+    pub fn num_values(&self) -> u64 {
+        self.n
+    }
+    /// The underlying binary value, always below `num_values()`.
+    pub fn as_ndu(&self) -> &ndu {
+        &self.bits
+    }
+    pub fn value(&self) -> u64 {
+        self.bits.value()
+    }
+    fn check_domain(&self, other: &nddomain) {
+        assert_eq!(self.n, other.n, "nddomain size mismatch");
+    }
+}
+
+/// Addition modulo the size of the domain, by `ndu::add_mod`. Both
+/// operands must have the same domain.
+impl Add for nddomain {
+    type Output = nddomain;
+    fn add(self, other: nddomain) -> nddomain {
+        self.check_domain(&other);
+        nddomain { bits: self.bits.add_mod(other.bits, self.n), n: self.n }
+    }
+}
+
+/// Multiplication modulo the size of the domain, by `ndu::mul_mod`. Both
+/// operands must have the same domain.
+impl Mul for nddomain {
+    type Output = nddomain;
+    fn mul(self, other: nddomain) -> nddomain {
+        self.check_domain(&other);
+        nddomain { bits: self.bits.mul_mod(other.bits, self.n), n: self.n }
+    }
+}
+
+/// Compares the values; variables over different domains are compared as
+/// integers.
+impl NdEq for nddomain {
+    fn ndeq(&self, rhs: &nddomain) -> ndbool {
+        self.bits.ndeq(&rhs.bits)
+    }
+}
+
+/// Asserts that the endpoints of every edge get different colors, where
+/// vertex `i` has the color `colors[i]`.
+///
+/// Each edge takes one binary clause per color, forbidding both endpoints
+/// to take it, which is cheaper than `ndne`.
+pub fn assert_proper_coloring(edges: &[(usize, usize)], colors: &[ndenum]) {
+    NdMachine::with(|machine| {
+        for &(a, b) in edges {
+            machine.check_owned(&colors[a].cases);
+            machine.check_owned(&colors[b].cases);
+            for (x, y) in colors[a].cases.iter().zip(&colors[b].cases) {
+                machine.add_clause(&[!x.0, !y.0]);
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tests::{solve, solver};
+    use {count_solutions, init, ndassert_eq, ndassert_ne};
+
+    fn color(n: usize, k: usize, edges: &[(usize, usize)]) -> Option<Vec<usize>> {
+        init();
+        let colors: Vec<ndenum> = (0..n).map(|_| ndenum::fresh(k)).collect();
+        assert_proper_coloring(edges, &colors);
+        if solve() {
+            Some(colors.iter().map(|c| c.value()).collect())
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn test_ndenum_value() {
+        init();
+        let x = ndenum::fresh(4);
+        assert_eq!(x.num_cases(), 4);
+        ndassert(!x.is(0) & !x.is(1) & !x.is(3));
+        assert!(solve());
+        assert_eq!(x.value(), 2);
+        ndassert(!x.is(2));
+        assert!(!solve());
+    }
+
+    #[test]
+    fn test_ndenum_coloring() {
+        let path = [(0, 1), (1, 2), (2, 3)];
+        let colors = color(4, 3, &path).unwrap();
+        for &(a, b) in &path {
+            assert_ne!(colors[a], colors[b]);
+        }
+        assert!(colors.iter().all(|&c| c < 3));
+        assert!(color(4, 2, &path).is_some());
+
+        // A triangle needs three colors, and K4 needs four.
+        let triangle = [(0, 1), (1, 2), (2, 0)];
+        assert!(color(3, 2, &triangle).is_none());
+        assert!(color(3, 3, &triangle).is_some());
+        assert!(color(4, 3, &[(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)]).is_none());
+    }
+
+    #[test]
+    fn test_proper_coloring_cycles() {
+        let cycle = |n: usize| -> Vec<(usize, usize)> { (0..n).map(|i| (i, (i + 1) % n)).collect() };
+        // An even cycle is bipartite and alternates its two colors.
+        let colors = color(6, 2, &cycle(6)).unwrap();
+        assert!(colors.iter().enumerate().all(|(i, &c)| c == (colors[0] + i) % 2));
+        assert!(color(5, 2, &cycle(5)).is_none());
+        assert!(color(5, 3, &cycle(5)).is_some());
+        assert!(color(2, 3, &[(1, 1)]).is_none());
+    }
+
+    #[test]
+    fn test_ndenum_ne() {
+        init();
+        let x = ndenum::fresh(3);
+        let y = ndenum::fresh(3);
+        ndassert_ne(x.clone(), y.clone());
+        ndassert(x.is(1));
+        assert!(solve());
+        assert_ne!(y.value(), 1);
+    }
+
+    #[test]
+    fn test_ndenum_sizes_differ() {
+        init();
+        let x = ndenum::fresh(2);
+        let y = ndenum::fresh(3);
+        ndassert(x.is(0) & y.is(0));
+        let eq = x.ndeq(&y);
+        assert!(solve());
+        assert!(!eq.value());
+    }
+
+    #[test]
+    fn test_nddomain_count() {
+        init();
+        let x = nddomain::fresh(9);
+        assert_eq!((x.num_values(), x.as_ndu().width()), (9, 4));
+        let bits: Vec<ndbool> = x.as_ndu().bits().collect();
+        assert_eq!(count_solutions(&solver(), &bits), 9);
+
+        init();
+        let unit = nddomain::fresh(1);
+        assert_eq!(unit.as_ndu().width(), 0);
+        assert!(solve());
+        assert_eq!(unit.value(), 0);
+    }
+
+    #[test]
+    fn test_nddomain_arith() {
+        init();
+        let mut results = vec![];
+        for a in 0..7 {
+            for b in 0..7 {
+                let (x, y) = (nddomain::constant(a, 7), nddomain::constant(b, 7));
+                results.push((a, b, x.clone() + y.clone(), x * y));
+            }
+        }
+        assert!(solve());
+        for (a, b, sum, prod) in results {
+            assert_eq!(sum.value(), (a + b) % 7);
+            assert_eq!(prod.value(), a * b % 7);
+        }
+
+        // x + 4 == 2 (mod 5) has the single solution 3.
+        init();
+        let x = nddomain::fresh(5);
+        ndassert_eq(x.clone() + nddomain::constant(4, 5), nddomain::constant(2, 5));
+        assert!(solve());
+        assert_eq!(x.value(), 3);
+        ndassert_ne(x, nddomain::constant(3, 5));
+        assert!(!solve());
+    }
+}
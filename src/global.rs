@@ -0,0 +1,180 @@
+//! Global constraints over several integers.
+
+use card::at_most_k;
+use {lex_le, ndassert, ndbool, ndu, NdEq, NdOrd};
+
+/// How `all_different_by` encodes distinctness.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllDifferent {
+    /// `ndne` between every pair: `n * (n - 1) / 2` comparisons.
+    Pairwise,
+    /// For values known to lie in `0..domain`: one indicator per value and
+    /// candidate, each candidate taken at most once. Values outside the
+    /// domain make the result false.
+    OneHot { domain: u64 },
+}
+
+/// Returns an `ndbool` that is true exactly when `values` are pairwise
+/// distinct. Values of different widths are compared after zero-extension.
+pub fn all_different(values: &[ndu]) -> ndbool {
+    all_different_by(values, AllDifferent::Pairwise)
+}
+
+/// Like `all_different`, choosing the encoding.
+pub fn all_different_by(values: &[ndu], encoding: AllDifferent) -> ndbool {
+    match encoding {
+        AllDifferent::Pairwise => {
+            let mut distinct = vec![];
+            for (i, a) in values.iter().enumerate() {
+                for b in &values[i + 1..] {
+                    distinct.push(a.ndne(b));
+                }
+            }
+            ndbool::all(&distinct)
+        }
+        AllDifferent::OneHot { domain } => {
+            let mut columns: Vec<Vec<ndbool>> = (0..domain).map(|_| vec![]).collect();
+            let mut conjuncts = vec![];
+            for x in values {
+                let candidates = if x.width() >= 64 { domain } else { domain.min(1 << x.width()) };
+                let indicators: Vec<ndbool> = (0..candidates)
+                    .map(|v| x.ndeq(&ndu::constant(v, x.width())))
+                    .collect();
+                conjuncts.push(ndbool::any(&indicators));
+                for (column, indicator) in columns.iter_mut().zip(indicators) {
+                    column.push(indicator);
+                }
+            }
+            for column in &columns {
+                conjuncts.push(at_most_k(column, 1));
+            }
+            ndbool::all(&conjuncts)
+        }
+    }
+}
+
+/// Asserts that `values` are pairwise distinct.
+pub fn assert_all_different(values: &[ndu]) {
+    ndassert(all_different(values))
+}
+
+/// Breaks the symmetry between interchangeable values: within each group,
+/// asserts with `lex_le` that the bits of consecutive values, most
+/// significant first and extended to a common width, are in nondecreasing
+/// lexicographic order, which is their order as integers.
+///
+/// This removes solutions, but only those that permute the values of a
+/// group: of every class of solutions related by such permutations, the
+/// sorted one is kept. It is therefore sound only if the values of each
+/// group can be exchanged in every solution without affecting the others.
+pub fn assert_symmetry_break(groups: &[&[ndu]]) {
+    for group in groups {
+        let width = group.iter().map(ndu::width).max().unwrap_or(0);
+        let msb_first = |x: &ndu| -> Vec<ndbool> { x.clone().resize(width).bits.into_iter().rev().collect() };
+        for pair in group.windows(2) {
+            ndassert(lex_le(&msb_first(&pair[0]), &msb_first(&pair[1])));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tests::{solve, solver};
+    use {count_solutions, init, ndassert_eq};
+
+    fn sudoku_row(encoding: AllDifferent) {
+        init();
+        let row: Vec<ndu> = (0..9).map(|_| ndu::fresh(4)).collect();
+        for x in &row {
+            ndassert(x.ndge(&ndu::constant(1, 4)) & x.ndle(&ndu::constant(9, 4)));
+        }
+        let givens = [(0, 5), (2, 3), (4, 7), (5, 1), (8, 9)];
+        for &(i, v) in &givens {
+            ndassert_eq(row[i].clone(), ndu::constant(v, 4));
+        }
+        ndassert(all_different_by(&row, encoding));
+        assert!(solve());
+        let mut values: Vec<u64> = row.iter().map(|x| x.value()).collect();
+        for &(i, v) in &givens {
+            assert_eq!(values[i], v);
+        }
+        values.sort();
+        assert_eq!(values, (1..10).collect::<Vec<_>>());
+
+        // A repeated given makes the row infeasible.
+        ndassert_eq(row[1].clone(), ndu::constant(5, 4));
+        assert!(!solve());
+    }
+
+    #[test]
+    fn test_sudoku_row_pairwise() {
+        sudoku_row(AllDifferent::Pairwise);
+    }
+
+    #[test]
+    fn test_sudoku_row_one_hot() {
+        sudoku_row(AllDifferent::OneHot { domain: 10 });
+    }
+
+    #[test]
+    fn test_one_hot_domain() {
+        init();
+        let x = ndu::fresh(3);
+        let y = ndu::fresh(3);
+        ndassert(all_different_by(&[x.clone(), y.clone()], AllDifferent::OneHot { domain: 2 }));
+        assert!(solve());
+        let (x, y) = (x.value(), y.value());
+        assert!(x < 2 && y < 2 && x != y);
+    }
+
+    #[test]
+    fn test_assert_all_different() {
+        init();
+        let values: Vec<ndu> = (0..5).map(|_| ndu::fresh(2)).collect();
+        assert_all_different(&values);
+        assert!(!solve());
+        init();
+        let values: Vec<ndu> = (0..4).map(|_| ndu::fresh(2)).collect();
+        assert_all_different(&values);
+        assert!(solve());
+        let mut values: Vec<u64> = values.iter().map(|x| x.value()).collect();
+        values.sort();
+        assert_eq!(values, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_assert_symmetry_break() {
+        let count = |symmetry_break: bool| {
+            init();
+            let colors: Vec<ndu> = (0..3).map(|_| ndu::fresh(2)).collect();
+            let other: Vec<ndu> = (0..2).map(|_| ndu::fresh(1)).collect();
+            assert_all_different(&colors);
+            if symmetry_break {
+                assert_symmetry_break(&[&colors, &other]);
+            }
+            let bits: Vec<ndbool> = colors.iter().chain(&other).flat_map(|x| x.bits()).collect();
+            count_solutions(&solver(), &bits)
+        };
+        // 4 * 3 * 2 colorings, and 4 values of the pair.
+        assert_eq!(count(false), 24 * 4);
+        // The sorted colorings, and the sorted pairs.
+        assert_eq!(count(true), 4 * 3);
+
+        // Fixing one value makes the values no longer interchangeable, and
+        // the sorted solutions all violate it.
+        init();
+        let colors: Vec<ndu> = (0..3).map(|_| ndu::fresh(2)).collect();
+        assert_all_different(&colors);
+        ndassert_eq(colors[0].clone(), ndu::constant(3, 2));
+        assert_symmetry_break(&[&colors]);
+        assert!(!solve());
+
+        // Values of different widths are compared as integers.
+        init();
+        let mixed = [ndu::fresh(1), ndu::fresh(2)];
+        assert_symmetry_break(&[&mixed]);
+        let bits: Vec<ndbool> = mixed.iter().flat_map(|x| x.bits()).collect();
+        assert_eq!(count_solutions(&solver(), &bits), 7);
+    }
+}
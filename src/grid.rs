@@ -0,0 +1,142 @@
+//! Two-dimensional arrays of variables for puzzle modeling.
+
+use std::ops::Index;
+
+/// A `rows` x `cols` array of variables (`ndbool`, `ndu`, `ndenum`, ...),
+/// stored row by row.
+#[derive(Clone)]
+pub struct NdGrid<T> {
+    rows: usize,
+    cols: usize,
+    cells: Vec<T>,
+}
+
+impl<T> NdGrid<T> {
+    /// Creates the grid with `cell(r, c)` at row `r` and column `c`, e.g.
+    /// `NdGrid::fresh(9, 9, |_, _| ndu::fresh(4))`.
+    pub fn fresh<F: FnMut(usize, usize) -> T>(rows: usize, cols: usize, mut cell: F) -> Self {
+        let mut cells = Vec::with_capacity(rows * cols);
+        for r in 0..rows {
+            for c in 0..cols {
+                cells.push(cell(r, c));
+            }
+        }
+        NdGrid { rows, cols, cells }
+    }
+    pub fn num_rows(&self) -> usize {
+        self.rows
+    }
+    pub fn num_cols(&self) -> usize {
+        self.cols
+    }
+    /// Panics if the cell is outside the grid.
+    pub fn get(&self, r: usize, c: usize) -> &T {
+        assert!(r < self.rows && c < self.cols, "cell ({}, {}) outside a {}x{} grid", r, c, self.rows, self.cols);
+        &self.cells[r * self.cols + c]
+    }
+    pub fn row(&self, r: usize) -> &[T] {
+        assert!(r < self.rows, "row {} outside a {}x{} grid", r, self.rows, self.cols);
+        &self.cells[r * self.cols..(r + 1) * self.cols]
+    }
+    pub fn col<'a>(&'a self, c: usize) -> impl Iterator<Item = &'a T> + 'a {
+        assert!(c < self.cols, "column {} outside a {}x{} grid", c, self.rows, self.cols);
+        self.cells.iter().skip(c).step_by(self.cols)
+    }
+    /// The cells with `c - r == d`, from the top. Diagonals run from
+    /// `d = 1 - rows` (bottom-left corner) to `d = cols - 1` (top-right
+    /// corner); others are empty.
+    pub fn diagonal<'a>(&'a self, d: isize) -> impl Iterator<Item = &'a T> + 'a {
+        (0..self.rows).filter_map(move |r| {
+            let c = r as isize + d;
+            if c >= 0 && (c as usize) < self.cols { Some(self.get(r, c as usize)) } else { None }
+        })
+    }
+    /// The cells with `r + c == s`, from the top. Anti-diagonals run from
+    /// `s = 0` (top-left corner) to `s = rows + cols - 2` (bottom-right
+    /// corner); others are empty.
+    pub fn anti_diagonal<'a>(&'a self, s: usize) -> impl Iterator<Item = &'a T> + 'a {
+        (0..self.rows.min(s + 1)).filter_map(move |r| {
+            let c = s - r;
+            if c < self.cols { Some(self.get(r, c)) } else { None }
+        })
+    }
+    /// All cells, row by row.
+    pub fn cells(&self) -> &[T] {
+        &self.cells
+    }
+}
+
+/// `grid[(r, c)]` is `grid.get(r, c)`.
+impl<T> Index<(usize, usize)> for NdGrid<T> {
+    type Output = T;
+    fn index(&self, (r, c): (usize, usize)) -> &T {
+        self.get(r, c)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tests::solve;
+    use {assert_at_most_k, assert_exactly_one, init, ndbool};
+
+    fn bits<'a, I: Iterator<Item = &'a ndbool>>(cells: I) -> Vec<ndbool> {
+        cells.cloned().collect()
+    }
+
+    #[test]
+    fn test_accessors() {
+        let grid = NdGrid::fresh(3, 4, |r, c| 10 * r + c);
+        assert_eq!((grid.num_rows(), grid.num_cols()), (3, 4));
+        assert_eq!(*grid.get(2, 1), 21);
+        assert_eq!(grid[(1, 3)], 13);
+        assert_eq!(grid.row(1), [10, 11, 12, 13]);
+        assert_eq!(grid.col(2).cloned().collect::<Vec<_>>(), vec![2, 12, 22]);
+        assert_eq!(grid.diagonal(0).cloned().collect::<Vec<_>>(), vec![0, 11, 22]);
+        assert_eq!(grid.diagonal(2).cloned().collect::<Vec<_>>(), vec![2, 13]);
+        assert_eq!(grid.diagonal(-2).cloned().collect::<Vec<_>>(), vec![20]);
+        assert_eq!(grid.diagonal(4).count(), 0);
+        assert_eq!(grid.anti_diagonal(3).cloned().collect::<Vec<_>>(), vec![3, 12, 21]);
+        assert_eq!(grid.anti_diagonal(5).cloned().collect::<Vec<_>>(), vec![23]);
+        assert_eq!(grid.anti_diagonal(6).count(), 0);
+        assert_eq!(grid.cells().len(), 12);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_get_outside() {
+        NdGrid::fresh(2, 2, |r, c| r + c).get(0, 2);
+    }
+
+    #[test]
+    fn test_n_queens() {
+        init();
+        let n = 8;
+        let queens = NdGrid::fresh(n, n, |_, _| ndbool::fresh());
+        for r in 0..n {
+            assert_exactly_one(queens.row(r));
+        }
+        for c in 0..n {
+            assert_at_most_k(&bits(queens.col(c)), 1);
+        }
+        for d in 1 - n as isize..n as isize {
+            assert_at_most_k(&bits(queens.diagonal(d)), 1);
+        }
+        for s in 0..2 * n - 1 {
+            assert_at_most_k(&bits(queens.anti_diagonal(s)), 1);
+        }
+        assert!(solve());
+        let placed: Vec<(usize, usize)> = (0..n)
+            .flat_map(|r| (0..n).map(move |c| (r, c)))
+            .filter(|&(r, c)| queens[(r, c)].value())
+            .collect();
+        assert_eq!(placed.len(), n);
+        for (i, &(r0, c0)) in placed.iter().enumerate() {
+            for &(r1, c1) in &placed[i + 1..] {
+                assert!(r0 != r1 && c0 != c1);
+                assert_ne!(r0 as isize - c0 as isize, r1 as isize - c1 as isize);
+                assert_ne!(r0 + c0, r1 + c1);
+            }
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! Saving and loading models as JSON, requiring the `serde` feature.
+
+use std::collections::HashMap;
+
+use serde::de::Error;
+use serde_json::{self, Map, Value};
+
+use dimacs::make_assignment;
+use {Model, NdMachine};
+
+impl NdMachine {
+    /// Writes the last model as a JSON object mapping variable numbers, as
+    /// in `export_dimacs`, to their values: `{"1": true, "2": false}`.
+    /// Variables created by `named` are keyed by their name instead, unless
+    /// another variable has the same name or the name is a number; those
+    /// keep their number so that no two variables share a key.
+    ///
+    /// Returns `None` if there is no model.
+    pub fn to_json(&self) -> Option<String> {
+        let model = self.model.as_ref()?;
+        let keys: HashMap<usize, &str> = self.json_names().into_iter().map(|(name, var)| (var, name)).collect();
+        let mut map = Map::new();
+        for var in 0..model.vars.len() {
+            let key = keys.get(&var).map(|name| name.to_string()).unwrap_or_else(|| (var + 1).to_string());
+            map.insert(key, Value::Bool(model.assignment.get(model.vars[var])));
+        }
+        Some(Value::Object(map).to_string())
+    }
+    /// Replaces the model by one read from the output of `to_json`, so that
+    /// `value` and `check_model` can inspect it without solving.
+    ///
+    /// Variables missing from the JSON are false; keys that are not
+    /// variables of this machine are an error.
+    pub fn from_json(&mut self, json: &str) -> serde_json::Result<()> {
+        let map: Map<String, Value> = serde_json::from_str(json)?;
+        let by_name = self.json_names();
+        let mut values = vec![false; self.num_vars];
+        for (key, value) in map {
+            let var = match by_name.get(&key[..]) {
+                Some(&var) => Some(var + 1),
+                None => key.parse::<usize>().ok().filter(|v| (1..=self.num_vars).contains(v)),
+            };
+            match (var, value.as_bool()) {
+                (Some(var), Some(b)) => values[var - 1] = b,
+                _ => return Err(serde_json::Error::custom(format!("bad model entry {:?}: {}", key, value))),
+            }
+        }
+        let (_, vars) = self.to_instance(&[]);
+        let assignment = make_assignment(self.num_vars, |v| values[v - 1]);
+        self.model = Some(Model { assignment, vars });
+        Ok(())
+    }
+    /// The names used as keys by `to_json`, with their variables: those
+    /// given to a single variable and not parsing as a number.
+    fn json_names(&self) -> HashMap<&str, usize> {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for name in self.names.values() {
+            *counts.entry(&name[..]).or_insert(0) += 1;
+        }
+        self.names.iter()
+            .filter(|&(_, name)| counts[&name[..]] == 1 && name.parse::<usize>().is_err())
+            .map(|(&var, name)| (&name[..], var))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tests::solver;
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut machine = NdMachine::new();
+        let b0 = machine.fresh();
+        let b1 = machine.fresh();
+        let x = machine.xor(b0, b1);
+        machine.assert(x);
+        machine.assert(b1);
+        assert_eq!(machine.to_json(), None);
+        assert!(machine.solve_by(&solver()));
+        let json = machine.to_json().unwrap();
+        // The xor is stored as a negated xnor gate, variable 3.
+        assert_eq!(json, r#"{"1":false,"2":true,"3":false}"#);
+
+        machine.from_json(&json).unwrap();
+        assert!(machine.check_model());
+        assert!(!machine.value(b0) && machine.value(b1) && machine.value(x));
+
+        machine.from_json(r#"{"1": true, "2": true}"#).unwrap();
+        assert!(machine.value(b0));
+        assert!(!machine.check_model());
+
+        assert!(machine.from_json(r#"{"4": true}"#).is_err());
+        assert!(machine.from_json(r#"{"1": 1}"#).is_err());
+        assert!(machine.from_json("[]").is_err());
+    }
+
+    #[test]
+    fn test_json_names() {
+        let mut machine = NdMachine::new();
+        let a = machine.named("a");
+        let b = machine.fresh();
+        machine.assert(a);
+        machine.assert(!b);
+        assert!(machine.solve_by(&solver()));
+        let json = machine.to_json().unwrap();
+        assert_eq!(json, r#"{"2":false,"a":true}"#);
+        machine.from_json(r#"{"a": false, "2": true}"#).unwrap();
+        assert!(!machine.value(a) && machine.value(b));
+    }
+
+    #[test]
+    fn test_json_ambiguous_names() {
+        let mut machine = NdMachine::new();
+        let a1 = machine.named("a");
+        let a2 = machine.named("a");
+        let b = machine.fresh();
+        let three = machine.named("3");
+        machine.assert(a1);
+        machine.assert(!a2);
+        machine.assert(b);
+        machine.assert(!three);
+        assert!(machine.solve_by(&solver()));
+        let json = machine.to_json().unwrap();
+        assert_eq!(json, r#"{"1":true,"2":false,"3":true,"4":false}"#);
+        machine.from_json(&json).unwrap();
+        assert!(machine.check_model());
+        assert!(machine.from_json(r#"{"a": true}"#).is_err());
+        machine.from_json(r#"{"3": false, "4": true}"#).unwrap();
+        assert!(!machine.value(b) && machine.value(three));
+    }
+}
@@ -0,0 +1,108 @@
+//! Linear-feedback shift registers.
+
+use {ndbool, ndu};
+
+/// The structure of the register stepped by `lfsr_step`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lfsr {
+    /// The state shifts towards the most significant bit, and the XOR of
+    /// the tapped bits enters at bit 0.
+    Fibonacci,
+    /// The state shifts towards the most significant bit, and the bit
+    /// shifted out is XORed into the tapped bits.
+    Galois,
+}
+
+/// The state after one step of the register. `taps` are bit positions of
+/// `state`; with the taps of a primitive polynomial, both kinds cycle
+/// through all nonzero states. A step adds at most one XOR gate per tap.
+///
+/// Panics if a tap is outside the width.
+pub fn lfsr_step(state: &ndu, taps: &[usize], kind: Lfsr) -> ndu {
+    let width = state.width();
+    for &t in taps {
+        assert!(t < width, "tap {} outside an ndu of width {}", t, width);
+    }
+    if width == 0 {
+        return state.clone();
+    }
+    let mut bits = state.bits.clone();
+    let out = bits.pop().unwrap();
+    match kind {
+        Lfsr::Fibonacci => {
+            let tapped: Vec<ndbool> = taps.iter().map(|&t| state.bits[t]).collect();
+            bits.insert(0, ndbool::parity(&tapped));
+        }
+        Lfsr::Galois => {
+            bits.insert(0, if taps.contains(&0) { out } else { ndbool::f() });
+            for &t in taps.iter().filter(|&&t| t != 0) {
+                bits[t] ^= out;
+            }
+        }
+    }
+    ndu { bits }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tests::solve;
+    use {init, ndassert_eq};
+
+    fn reference(state: u64, taps: &[usize], width: usize, kind: Lfsr) -> u64 {
+        let mask: u64 = taps.iter().map(|&t| 1 << t).sum();
+        let shifted = (state << 1) & ((1 << width) - 1);
+        match kind {
+            Lfsr::Fibonacci => shifted | ((state & mask).count_ones() as u64 & 1),
+            Lfsr::Galois => if state >> (width - 1) == 1 { shifted ^ mask } else { shifted },
+        }
+    }
+
+    #[test]
+    fn test_lfsr_step() {
+        // x^8 + x^6 + x^5 + x^4 + 1
+        let taps = [7, 5, 4, 3];
+        for &kind in &[Lfsr::Fibonacci, Lfsr::Galois] {
+            init();
+            let mut state = ndu::constant(0xa5, 8);
+            let mut states = vec![];
+            for _ in 0..20 {
+                state = lfsr_step(&state, &taps, kind);
+                states.push(state.clone());
+            }
+            assert!(solve());
+            let mut expected = 0xa5;
+            for s in &states {
+                expected = reference(expected, &taps, 8, kind);
+                assert_eq!(s.value(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_lfsr_period() {
+        // x^4 + x + 1 is primitive: the period is 15.
+        for &(kind, taps) in &[(Lfsr::Fibonacci, [3, 2]), (Lfsr::Galois, [1, 0])] {
+            init();
+            let start = ndu::fresh(4);
+            let mut state = start.clone();
+            let mut states = vec![];
+            for _ in 0..15 {
+                state = lfsr_step(&state, &taps, kind);
+                states.push(state.clone());
+            }
+            ndassert_eq(start.clone(), ndu::constant(1, 4));
+            assert!(solve());
+            let values: Vec<u64> = states.iter().map(|s| s.value()).collect();
+            assert_eq!(values[14], 1);
+            assert!(!values[..14].contains(&1));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "tap 4 outside")]
+    fn test_lfsr_tap_out_of_range() {
+        init();
+        lfsr_step(&ndu::fresh(4), &[4], Lfsr::Galois);
+    }
+}
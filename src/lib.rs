@@ -1,29 +1,142 @@
 extern crate sat;
+extern crate tempfile;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "serde")]
+extern crate serde_json;
+#[cfg(feature = "varisat")]
+extern crate varisat;
+#[cfg(feature = "tokio")]
+extern crate tokio;
 
 use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+#[cfg(debug_assertions)]
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not};
+use std::time::Instant;
 
 use sat::{Instance, Literal, Assignment};
 use sat::solver::Solver;
 
+use encoding::GateDef;
+
+#[cfg(feature = "varisat")]
+pub use backend::{IncrementalSolver, VarisatSolver};
+pub use array::{array_read, array_read_by, array_read_ndu, array_read_ndu_by, decode, priority_encoder, OutOfRange};
+pub use card::{assert_at_most_k, assert_exactly_one, at_least_k, at_most_k, compare_swap, compare_swap_ndu, exactly_k, pb_eq, pb_ge, pb_le, popcount, sort_bits};
+pub use dimacs::{export_dimacs, export_wcnf};
+pub use encoding::{set_comparator, set_encoding, Comparator, Encoding};
+pub use external::{DimacsBuilder, ExternalSolver, SolveStats};
+pub use finite::{assert_proper_coloring, nddomain, ndenum};
+pub use global::{all_different, all_different_by, assert_all_different, assert_symmetry_break, AllDifferent};
+pub use grid::NdGrid;
+pub use lfsr::{lfsr_step, Lfsr};
+pub use ndint::{full_adder, half_adder, ndi, ndu};
+#[cfg(feature = "tokio")]
+pub use nonblocking::SolveFuture;
+pub use portfolio::PortfolioSolver;
+pub use progress::{set_progress_callback, ProgressEvent};
+pub use search::{add_blocking_clause, count_solutions, count_solutions_capped, maximize, minimize, minimize_value, ndassert_soft, solutions, solve_maxsat, Solutions};
+pub use shared::SharedNdMachine;
+pub use tagged::{ndassert_tagged, retract, unsat_core};
+
+#[cfg(feature = "varisat")]
+mod backend;
+mod array;
+mod card;
+mod dimacs;
+mod dot;
+mod encoding;
+mod external;
+mod finite;
+mod global;
+mod grid;
+#[cfg(feature = "serde")]
+mod json;
+mod lfsr;
+mod ndint;
+#[cfg(feature = "tokio")]
+mod nonblocking;
+mod portfolio;
+mod progress;
+mod search;
+mod shared;
+mod tagged;
+
+/// Replaces the thread-local machine with a new one, discarding its
+/// constraints and settings. The machine is created on first use, so this
+/// is only needed to start over.
 pub fn init() {
     NdMachine::with_opt(|machine| {
         *machine = Some(NdMachine::new());
     });
 }
 
+/// Clears all constraints of the thread-local machine.
+///
+/// Every `ndbool` created before the reset becomes invalid.
+pub fn reset() {
+    NdMachine::with(|machine| machine.reset())
+}
+
+/// Solves the thread-local machine. See `NdMachine::solve_by`.
 pub fn solve_by<T: Solver>(solver: &T) -> bool {
-    NdMachine::with(|machine| {
-        let assignment = solver.solve(&machine.instance);
-        machine.assignment = assignment;
-        machine.assignment.is_some()
-    })
+    NdMachine::with(|machine| machine.solve_by(solver))
+}
+
+pub fn solve_by_result<T: Solver>(solver: &T) -> SolveResult {
+    NdMachine::with(|machine| machine.solve_by_result(solver))
+}
+
+/// Solves the thread-local machine with `assumptions` temporarily forced
+/// true. See `NdMachine::solve_under_assumptions`.
+pub fn solve_under_assumptions<T: Solver>(solver: &T, assumptions: &[ndbool]) -> SolveResult {
+    NdMachine::with(|machine| machine.solve_under_assumptions(solver, assumptions))
+}
+
+/// The CNF size of the thread-local machine. See `NdMachine::stats`.
+pub fn stats() -> Stats {
+    NdMachine::with(|machine| machine.stats())
+}
+
+/// Why the last solve of the thread-local machine returned `Unknown`.
+pub fn last_solve_error() -> Option<String> {
+    NdMachine::with(|machine| machine.last_error().map(str::to_string))
 }
 
 pub fn ndassert(b: ndbool) {
-    NdMachine::with(|machine| {
-        machine.instance.assert_any(&[b.0]);
-    })
+    NdMachine::with(|machine| machine.assert(b))
+}
+
+/// Asserts `a => b` as the single clause `!a | b`.
+pub fn ndassert_implies(a: ndbool, b: ndbool) {
+    NdMachine::with(|machine| machine.assert_implies(a, b))
+}
+
+/// Asserts each of `bits` as a unit clause. Does nothing for an empty
+/// slice.
+pub fn ndassert_all(bits: &[ndbool]) {
+    NdMachine::with(|machine| machine.assert_all(bits))
+}
+
+/// Asserts that one of `bits` holds, as a single clause. An empty slice
+/// makes the instance unsatisfiable.
+pub fn ndassert_any(bits: &[ndbool]) {
+    NdMachine::with(|machine| machine.assert_any(bits))
+}
+
+/// Enforces `b` only when `guard` holds, as the single clause
+/// `!guard | b`. This is `ndassert_implies` read as a guard.
+pub fn ndassert_if(guard: ndbool, b: ndbool) {
+    ndassert_implies(guard, b)
+}
+
+/// Enforces `lhs == rhs` only when `guard` holds.
+pub fn ndassert_eq_if<T: NdEq<U>, U>(guard: ndbool, lhs: T, rhs: U) {
+    ndassert_if(guard, lhs.ndeq(&rhs));
 }
 
 pub fn ndassert_eq<T: NdEq<U>, U>(lhs: T, rhs: U) {
@@ -34,90 +147,697 @@ pub fn ndassert_ne<T: NdEq<U>, U>(lhs: T, rhs: U) {
     ndassert(lhs.ndne(&rhs));
 }
 
+pub fn ndassert_lt<T: NdOrd<U>, U>(lhs: T, rhs: U) {
+    ndassert(lhs.ndlt(&rhs));
+}
+
+pub fn ndassert_le<T: NdOrd<U>, U>(lhs: T, rhs: U) {
+    ndassert(lhs.ndle(&rhs));
+}
+
+
+/// Outcome of a solver invocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolveResult {
+    /// A model was found and can be read with `value`.
+    Sat,
+    /// The solver proved that no model exists.
+    Unsat,
+    /// The solver failed to give an answer (e.g. it crashed or could not be
+    /// started).
+    Unknown,
+}
 
+/// Size of the CNF accumulated by a machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Stats {
+    pub num_variables: usize,
+    pub num_clauses: usize,
+    pub max_clause_len: usize,
+}
+
+/// The panic payload by which a solver reports that it could not answer,
+/// such as a missing program or a timeout.
+///
+/// `sat::Solver::solve` has no way to return an error, so solvers raise
+/// this with `SolverError::raise` and `solve_by_result` turns it into
+/// `SolveResult::Unknown`, keeping the message for `last_error`. Any other
+/// panic of a solver is a bug and is propagated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SolverError(pub String);
+
+impl SolverError {
+    /// Unwinds with `SolverError(message)`, without running the panic hook.
+    pub fn raise<S: Into<String>>(message: S) -> ! {
+        panic::resume_unwind(Box::new(SolverError(message.into())))
+    }
+}
+
+impl fmt::Display for SolverError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// A SAT instance together with its last solution.
+///
+/// The free functions and the operators on `ndbool` work on a thread-local
+/// default machine, created on first use and replaced by `init()`. An
+/// `NdMachine` can also be owned directly and driven through its methods,
+/// which allows several independent problems to live in the same thread.
+///
+/// An `ndbool` is only meaningful for the machine that created it. Passing it
+/// to another machine (including the thread-local one) is a logic error and
+/// produces unspecified results.
 pub struct NdMachine {
-    instance: Instance,
-    assignment: Option<Assignment>,
+    num_vars: usize,
+    clauses: Vec<Vec<Lit>>,
+    model: Option<Model>,
+    t_lit: Option<Lit>,
+    f_lit: Option<Lit>,
+    tags: Vec<(String, Lit)>,
+    last_error: Option<String>,
+    gates: HashMap<(Gate, Vec<Lit>), Lit>,
+    encoding: Encoding,
+    comparator: Comparator,
+    defs: HashMap<usize, GateDef>,
+    required: HashSet<Lit>,
+    names: HashMap<usize, String>,
+    soft: Vec<(Lit, u64)>,
+    id: MachineId,
+    progress: Option<Box<dyn FnMut(ProgressEvent) + Send>>,
+}
+
+/// The kinds of gates shared by `NdMachine::gate`.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+enum Gate {
+    And,
+    Or,
+    Xnor,
+    Select,
+}
+
+/// Identifies the machine an `ndbool` belongs to. It is empty in release
+/// builds, where the ownership checks are compiled out.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct MachineId {
+    #[cfg(debug_assertions)]
+    id: usize,
+}
+
+impl MachineId {
+    fn fresh() -> Self {
+        #[cfg(debug_assertions)]
+        {
+            static NEXT: AtomicUsize = AtomicUsize::new(0);
+            MachineId { id: NEXT.fetch_add(1, Ordering::Relaxed) }
+        }
+        #[cfg(not(debug_assertions))]
+        MachineId {}
+    }
+}
+
+/// A variable index together with a polarity, as found in the clauses of
+/// a machine.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct Lit {
+    var: usize,
+    negated: bool,
+}
+
+impl Lit {
+    /// The index of the variable, counting from 0 in order of creation.
+    pub fn var(self) -> usize {
+        self.var
+    }
+    pub fn is_negated(self) -> bool {
+        self.negated
+    }
+    /// The literal as written by `export_dimacs`: the variable counted
+    /// from 1, negative if negated.
+    pub fn to_dimacs(self) -> isize {
+        let n = self.var as isize + 1;
+        if self.negated { -n } else { n }
+    }
+}
+
+impl Not for Lit {
+    type Output = Lit;
+    fn not(self) -> Lit {
+        Lit {
+            negated: !self.negated,
+            ..self
+        }
+    }
+}
+
+/// A solution together with the `sat` literals of the instance it solves.
+struct Model {
+    assignment: Assignment,
+    vars: Vec<Literal>,
+}
+
+impl Model {
+    /// Returns `None` for variables created after the model was found.
+    fn get(&self, l: Lit) -> Option<bool> {
+        self.vars.get(l.var).map(|&v| self.assignment.get(if l.negated { !v } else { v }))
+    }
 }
 
 impl NdMachine {
-    fn new() -> Self {
+    pub fn new() -> Self {
         NdMachine {
-            instance: Instance::new(),
-            assignment: None,
+            num_vars: 0,
+            clauses: vec![],
+            model: None,
+            t_lit: None,
+            f_lit: None,
+            tags: vec![],
+            last_error: None,
+            gates: HashMap::new(),
+            encoding: Encoding::Tseitin,
+            comparator: Comparator::Chain,
+            defs: HashMap::new(),
+            required: HashSet::new(),
+            names: HashMap::new(),
+            soft: vec![],
+            id: MachineId::fresh(),
+            progress: None,
+        }
+    }
+    /// Discards all variables, constraints and the last solution.
+    ///
+    /// Every `ndbool` created before the reset becomes invalid, and using
+    /// one panics in debug builds.
+    pub fn reset(&mut self) {
+        self.num_vars = 0;
+        self.clauses.clear();
+        self.model = None;
+        self.t_lit = None;
+        self.f_lit = None;
+        self.tags.clear();
+        self.last_error = None;
+        self.gates.clear();
+        self.defs.clear();
+        self.required.clear();
+        self.names.clear();
+        self.soft.clear();
+        self.id = MachineId::fresh();
+    }
+    /// A copy of the constraints, variable names and tags, without the
+    /// current model, to be extended and solved independently.
+    ///
+    /// Every `ndbool` of this machine denotes the same variable in the fork.
+    pub fn fork(&self) -> NdMachine {
+        NdMachine {
+            num_vars: self.num_vars,
+            clauses: self.clauses.clone(),
+            model: None,
+            t_lit: self.t_lit,
+            f_lit: self.f_lit,
+            tags: self.tags.clone(),
+            last_error: None,
+            gates: self.gates.clone(),
+            encoding: self.encoding,
+            comparator: self.comparator,
+            defs: self.defs.clone(),
+            required: self.required.clone(),
+            names: self.names.clone(),
+            soft: self.soft.clone(),
+            id: self.id,
+            progress: None,
+        }
+    }
+    /// The size of the CNF built so far, counted like the header of
+    /// `export_dimacs`.
+    pub fn stats(&self) -> Stats {
+        let max_clause_len = self.clauses.iter().map(|c| c.len()).max().unwrap_or(0);
+        Stats {
+            num_variables: self.num_vars,
+            num_clauses: self.clauses.len() + self.tags.len(),
+            max_clause_len: if self.tags.is_empty() { max_clause_len } else { max_clause_len.max(1) },
+        }
+    }
+    /// The clauses built so far, followed by the selectors of tagged
+    /// assertions as unit clauses: the clauses of `export_dimacs`, in the
+    /// same order. The literals of each clause are in the order they were
+    /// added, which for gates is the order of their defining clauses.
+    pub fn clauses(&self) -> impl Iterator<Item = &[Lit]> {
+        self.clauses.iter().map(|c| &c[..]).chain(self.tags.iter().map(|(_, l)| std::slice::from_ref(l)))
+    }
+    /// Solves the instance, returning whether it is satisfiable.
+    ///
+    /// # Panics
+    ///
+    /// Panics with the message of `last_error` if the solver fails, so that
+    /// a failure is never mistaken for UNSAT. Use `solve_by_result` to
+    /// handle failures instead.
+    pub fn solve_by<T: Solver>(&mut self, solver: &T) -> bool {
+        match self.solve_by_result(solver) {
+            SolveResult::Sat => true,
+            SolveResult::Unsat => false,
+            SolveResult::Unknown => panic!("solver failed: {}", self.last_error.as_deref().unwrap_or("unknown error")),
+        }
+    }
+    /// Solves the instance, distinguishing solver failures from UNSAT.
+    ///
+    /// Solvers report failures (such as a missing or crashing external
+    /// program) by panicking with a `SolverError`, as `ExternalSolver`,
+    /// `VarisatSolver` and `PortfolioSolver` do. Such a panic is caught and
+    /// reported as `SolveResult::Unknown`, and its message is kept for
+    /// `last_error`. Other panics, including those of
+    /// `sat::solver::Dimacs`, are propagated.
+    ///
+    /// Instances with an empty clause or two opposite unit clauses are
+    /// reported `Unsat` without invoking the solver.
+    pub fn solve_by_result<T: Solver>(&mut self, solver: &T) -> SolveResult {
+        self.solve_with_units(solver, &[])
+    }
+    /// The assignment found by the last successful solve.
+    ///
+    /// Variable `i` of the assignment is the `i`-th variable of the machine,
+    /// numbered like in `export_dimacs` but from zero.
+    pub fn last_model(&self) -> Option<&Assignment> {
+        self.model.as_ref().map(|model| &model.assignment)
+    }
+    /// Whether the current model satisfies every clause of the machine,
+    /// including the selectors of tagged assertions. False if there is no
+    /// model.
+    pub fn check_model(&self) -> bool {
+        let model = match self.model {
+            Some(ref model) => model,
+            None => return false,
+        };
+        let holds = |l: &Lit| model.get(*l) == Some(true);
+        self.clauses.iter().all(|c| c.iter().any(&holds)) && self.tags.iter().all(|(_, l)| holds(l))
+    }
+    /// The message of the failure that made the last solve return
+    /// `Unknown`, if it did. `ExternalSolver` includes the solver's stderr.
+    pub fn last_error(&self) -> Option<&str> {
+        self.last_error.as_deref()
+    }
+    /// Solves the instance with `assumptions` additionally forced true.
+    ///
+    /// The assumptions are passed to the solver as extra unit clauses of the
+    /// instance handed to it; the constraints of the machine are left
+    /// unchanged. On `Sat` the model satisfies the assumptions.
+    pub fn solve_under_assumptions<T: Solver>(&mut self, solver: &T, assumptions: &[ndbool]) -> SolveResult {
+        let units: Vec<Lit> = assumptions.iter().map(|&b| self.lit(b)).collect();
+        self.solve_with_units(solver, &units)
+    }
+    /// Solves with the given unit clauses and the selectors of all tags.
+    fn solve_with_units<T: Solver>(&mut self, solver: &T, units: &[Lit]) -> SolveResult {
+        let mut units = units.to_vec();
+        units.extend(self.tags.iter().map(|&(_, selector)| selector));
+        self.solve_with_units_only(solver, &units)
+    }
+    fn solve_with_units_only<T: Solver>(&mut self, solver: &T, units: &[Lit]) -> SolveResult {
+        self.report(ProgressEvent::SolveStarted);
+        let start = Instant::now();
+        let result = self.run_solver(solver, units);
+        self.report(ProgressEvent::SolveFinished { result, elapsed: start.elapsed() });
+        result
+    }
+    fn run_solver<T: Solver>(&mut self, solver: &T, units: &[Lit]) -> SolveResult {
+        for &unit in units {
+            self.require(unit);
+        }
+        if self.trivially_unsat(units) {
+            self.model = None;
+            self.last_error = None;
+            return SolveResult::Unsat;
+        }
+        let (instance, vars) = self.to_instance(units);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| solver.solve(&instance)));
+        self.last_error = None;
+        match result {
+            Ok(Some(assignment)) => {
+                self.model = Some(Model { assignment, vars });
+                SolveResult::Sat
+            }
+            Ok(None) => {
+                self.model = None;
+                SolveResult::Unsat
+            }
+            Err(payload) => {
+                self.model = None;
+                match payload.downcast::<SolverError>() {
+                    Ok(error) => {
+                        self.last_error = Some(error.0);
+                        SolveResult::Unknown
+                    }
+                    Err(payload) => panic::resume_unwind(payload),
+                }
+            }
+        }
+    }
+    /// Whether the clauses plus `units` contain an empty clause or two
+    /// opposite unit clauses, which needs no solver to refute. No
+    /// propagation is done.
+    fn trivially_unsat(&self, units: &[Lit]) -> bool {
+        let mut seen = HashSet::new();
+        for clause in self.clauses.iter().map(|c| &c[..]).chain(units.iter().map(std::slice::from_ref)) {
+            match *clause {
+                [] => return true,
+                [unit] => {
+                    if seen.contains(&!unit) {
+                        return true;
+                    }
+                    seen.insert(unit);
+                }
+                _ => {}
+            }
+        }
+        false
+    }
+    /// Builds a `sat::Instance` of the clauses plus the given unit clauses.
+    fn to_instance(&self, units: &[Lit]) -> (Instance, Vec<Literal>) {
+        let mut instance = Instance::new();
+        let vars: Vec<Literal> = (0..self.num_vars).map(|_| instance.fresh_var()).collect();
+        let lit = |l: &Lit| if l.negated { !vars[l.var] } else { vars[l.var] };
+        for clause in &self.clauses {
+            let lits: Vec<Literal> = clause.iter().map(&lit).collect();
+            instance.assert_any(&lits);
+        }
+        for unit in units {
+            instance.assert_any(&[lit(unit)]);
+        }
+        (instance, vars)
+    }
+    fn fresh_lit(&mut self) -> Lit {
+        let var = self.num_vars;
+        self.num_vars += 1;
+        Lit { var, negated: false }
+    }
+    fn add_clause(&mut self, lits: &[Lit]) {
+        for &lit in lits {
+            self.require(lit);
+        }
+        self.clauses.push(lits.to_vec());
+    }
+    /// The literal of `b`, checking in debug builds that `b` was created by
+    /// this machine.
+    pub fn lit(&self, b: ndbool) -> Lit {
+        debug_assert_eq!(b.1, self.id, "ndbool used with a machine that did not create it");
+        b.0
+    }
+    /// Checks the owner of each of `bits` like `lit`.
+    fn check_owned(&self, bits: &[ndbool]) {
+        for &b in bits {
+            self.lit(b);
+        }
+    }
+    fn wrap(&self, l: Lit) -> ndbool {
+        ndbool(l, self.id)
+    }
+    pub fn assert(&mut self, b: ndbool) {
+        let l = self.lit(b);
+        self.add_clause(&[l]);
+    }
+    /// Asserts `a => b` as the single clause `!a | b`.
+    pub fn assert_implies(&mut self, a: ndbool, b: ndbool) {
+        let (a, b) = (self.lit(a), self.lit(b));
+        self.add_clause(&[!a, b]);
+    }
+    /// Asserts each of `bits` as a unit clause, without the gates of
+    /// `all`.
+    pub fn assert_all(&mut self, bits: &[ndbool]) {
+        for &b in bits {
+            self.assert(b);
+        }
+    }
+    /// Asserts the single clause `bits[0] | bits[1] | ...`, without the
+    /// gates of `any`. An empty slice adds the empty clause.
+    pub fn assert_any(&mut self, bits: &[ndbool]) {
+        let lits: Vec<Lit> = bits.iter().map(|&b| self.lit(b)).collect();
+        self.add_clause(&lits);
+    }
+    /// Returns the canonical true literal, allocating it on first use.
+    pub fn t(&mut self) -> ndbool {
+        if let Some(l) = self.t_lit {
+            return self.wrap(l);
+        }
+        let l = self.fresh_lit();
+        self.add_clause(&[l]);
+        self.model = None;
+        self.t_lit = Some(l);
+        self.wrap(l)
+    }
+    /// Returns the canonical false literal, allocating it on first use.
+    pub fn f(&mut self) -> ndbool {
+        if let Some(l) = self.f_lit {
+            return self.wrap(l);
+        }
+        let l = self.fresh_lit();
+        self.add_clause(&[!l]);
+        self.model = None;
+        self.f_lit = Some(l);
+        self.wrap(l)
+    }
+    pub fn fresh(&mut self) -> ndbool {
+        let l = self.fresh_lit();
+        self.wrap(l)
+    }
+    /// A fresh variable carrying a name for debugging, shown by
+    /// `export_dimacs` and `to_json`. Names should be unique.
+    pub fn named(&mut self, name: &str) -> ndbool {
+        let l = self.fresh_lit();
+        self.names.insert(l.var, name.to_string());
+        self.wrap(l)
+    }
+    /// The name given to the variable of `b` by `named`, if any.
+    pub fn name(&self, b: ndbool) -> Option<&str> {
+        self.names.get(&self.lit(b).var).map(|s| &s[..])
+    }
+    /// Lifts a Rust `bool` into the machine.
+    ///
+    /// This returns the shared literals of `t()` and `f()`, so repeated calls
+    /// do not grow the instance.
+    pub fn constant(&mut self, b: bool) -> ndbool {
+        if b { self.t() } else { self.f() }
+    }
+    pub fn value(&self, b: ndbool) -> bool {
+        self.try_value(b).expect("No solution!")
+    }
+    /// Returns `None` instead of panicking when there is no current model.
+    pub fn try_value(&self, b: ndbool) -> Option<bool> {
+        self.model.as_ref().and_then(|model| self.eval(model, self.lit(b)))
+    }
+    /// The values of `vars` in the current model, in order, or `None` if
+    /// there is no current model.
+    pub fn project(&self, vars: &[ndbool]) -> Option<Vec<bool>> {
+        vars.iter().map(|&b| self.try_value(b)).collect()
+    }
+    /// Returns `a & b`: one variable and three clauses, unless the same
+    /// gate already exists.
+    pub fn and(&mut self, a: ndbool, b: ndbool) -> ndbool {
+        self.gate(Gate::And, &[a, b], |l| vec![
+            vec![!a.0, !b.0, l],
+            vec![a.0, !l],
+            vec![b.0, !l],
+        ])
+    }
+    /// Returns `a | b`: one variable and three clauses, unless the same
+    /// gate already exists.
+    pub fn or(&mut self, a: ndbool, b: ndbool) -> ndbool {
+        self.gate(Gate::Or, &[a, b], |l| vec![
+            vec![a.0, b.0, !l],
+            vec![!a.0, l],
+            vec![!b.0, l],
+        ])
+    }
+    /// Returns `!a | b`: one variable and three clauses, unless the same
+    /// gate already exists.
+    pub fn implies(&mut self, a: ndbool, b: ndbool) -> ndbool {
+        self.or(!a, b)
+    }
+    /// Returns `then` if `cond` holds and `els` otherwise: one variable and
+    /// four clauses.
+    pub fn select(&mut self, cond: ndbool, then: ndbool, els: ndbool) -> ndbool {
+        self.gate(Gate::Select, &[cond, then, els], |l| vec![
+            vec![!cond.0, !then.0, l],
+            vec![!cond.0, then.0, !l],
+            vec![cond.0, !els.0, l],
+            vec![cond.0, els.0, !l],
+        ])
+    }
+    /// Returns `!(a & b)`: one variable and three clauses.
+    pub fn nand(&mut self, a: ndbool, b: ndbool) -> ndbool {
+        !self.and(a, b)
+    }
+    /// Returns `!(a | b)`: one variable and three clauses.
+    pub fn nor(&mut self, a: ndbool, b: ndbool) -> ndbool {
+        !self.or(a, b)
+    }
+    /// Returns `a ^ b`: one variable and four clauses.
+    pub fn xor(&mut self, a: ndbool, b: ndbool) -> ndbool {
+        !self.xnor(a, b)
+    }
+    /// Returns `a == b`: one variable and four clauses.
+    pub fn xnor(&mut self, a: ndbool, b: ndbool) -> ndbool {
+        self.gate(Gate::Xnor, &[a, b], |l| vec![
+            vec![!a.0, !b.0, l],
+            vec![a.0, b.0, l],
+            vec![!a.0, b.0, !l],
+            vec![a.0, !b.0, !l],
+        ])
+    }
+    /// Returns the output of `kind` over `inputs`, reusing an identical
+    /// gate if one was built before. Otherwise `encode` gives the clauses
+    /// defining a fresh output literal, which are added according to the
+    /// current `Encoding`.
+    ///
+    /// The inputs of commutative gates are sorted, so `a & b` and `b & a`
+    /// share their output.
+    fn gate<F: FnOnce(Lit) -> Vec<Vec<Lit>>>(&mut self, kind: Gate, inputs: &[ndbool], encode: F) -> ndbool {
+        let mut key: Vec<Lit> = inputs.iter().map(|&b| self.lit(b)).collect();
+        if kind != Gate::Select {
+            key.sort();
+        }
+        if let Some(&l) = self.gates.get(&(kind, key.clone())) {
+            return self.wrap(l);
+        }
+        let l = self.fresh_lit();
+        let clauses = encode(l);
+        match self.encoding {
+            Encoding::Tseitin => {
+                for clause in &clauses {
+                    self.add_clause(clause);
+                }
+            }
+            Encoding::PlaistedGreenbaum => {
+                let inputs = inputs.iter().map(|b| b.0).collect();
+                self.defs.insert(l.var, GateDef { kind, inputs, clauses });
+            }
+        }
+        self.gates.insert((kind, key), l);
+        self.model = None;
+        self.wrap(l)
+    }
+    /// Returns the conjunction of `bits`, true for an empty slice.
+    ///
+    /// The gates form a balanced tree of depth `ceil(log2(n))`.
+    pub fn all(&mut self, bits: &[ndbool]) -> ndbool {
+        let t = self.t();
+        self.balanced(bits, t, NdMachine::and)
+    }
+    /// Returns the disjunction of `bits`, false for an empty slice.
+    pub fn any(&mut self, bits: &[ndbool]) -> ndbool {
+        let f = self.f();
+        self.balanced(bits, f, NdMachine::or)
+    }
+    /// Returns the XOR of `bits`, false for an empty slice.
+    pub fn parity(&mut self, bits: &[ndbool]) -> ndbool {
+        let f = self.f();
+        self.balanced(bits, f, NdMachine::xor)
+    }
+    fn balanced(&mut self, bits: &[ndbool], empty: ndbool, op: fn(&mut Self, ndbool, ndbool) -> ndbool) -> ndbool {
+        match bits.len() {
+            0 => empty,
+            1 => bits[0],
+            n => {
+                let (l, r) = bits.split_at(n / 2);
+                let l = self.balanced(l, empty, op);
+                let r = self.balanced(r, empty, op);
+                op(self, l, r)
+            }
         }
     }
     fn with_opt<R, F: FnOnce(&mut Option<Self>) -> R>(f: F) -> R {
         thread_local! {
-            static MACHINE : RefCell<Option<NdMachine>> = RefCell::new(None);
+            static MACHINE : RefCell<Option<NdMachine>> = const { RefCell::new(None) };
         }
         MACHINE.with(|machine| {
             f(&mut machine.borrow_mut())
         })
     }
     fn with<R, F: FnOnce(&mut Self) -> R>(f: F) -> R {
-        Self::with_opt(|this_opt| {
-            if let Some(ref mut this) = *this_opt {
-                f(this)
-            } else {
-                panic!("ndmachine::init() should be called first")
-            }
-        })
+        Self::with_opt(|this_opt| f(this_opt.get_or_insert_with(NdMachine::new)))
+    }
+}
+
+impl Default for NdMachine {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 #[allow(non_camel_case_types)]
 #[derive(Copy, Clone)]
-pub struct ndbool(Literal);
+pub struct ndbool(Lit, MachineId);
 
 impl ndbool {
     pub fn t() -> Self {
-        NdMachine::with(|machine| {
-            let l = machine.instance.fresh_var();
-            machine.instance.assert_any(&[l]);
-            machine.assignment = None;
-            ndbool(l)
-        })
+        NdMachine::with(|machine| machine.t())
     }
     pub fn f() -> Self {
-        NdMachine::with(|machine| {
-            let l = machine.instance.fresh_var();
-            machine.instance.assert_any(&[!l]);
-            machine.assignment = None;
-            ndbool(l)
-        })
+        NdMachine::with(|machine| machine.f())
     }
     pub fn fresh() -> Self {
-        NdMachine::with(|machine| {
-            ndbool(machine.instance.fresh_var())
-        })
+        NdMachine::with(|machine| machine.fresh())
+    }
+    /// A fresh variable carrying a name. See `NdMachine::named`.
+    pub fn named(name: &str) -> Self {
+        NdMachine::with(|machine| machine.named(name))
+    }
+    /// Lifts a Rust `bool` into the machine.
+    pub fn constant(b: bool) -> Self {
+        NdMachine::with(|machine| machine.constant(b))
     }
     pub fn value(self) -> bool {
-        NdMachine::with(|machine| {
-            let assignment = machine.assignment.as_ref().expect("No solution!");
-            assignment.get(self.0)
-        })
+        self.try_value().expect("No solution!")
+    }
+    /// Returns `None` instead of panicking when there is no current model.
+    pub fn try_value(self) -> Option<bool> {
+        NdMachine::with(|machine| machine.try_value(self))
+    }
+    /// Returns `!self | other`: one variable and three clauses, unless the
+    /// same gate already exists.
+    pub fn implies(self, other: ndbool) -> ndbool {
+        NdMachine::with(|machine| machine.implies(self, other))
+    }
+    /// Returns `then` if `cond` holds and `els` otherwise: one variable and
+    /// four clauses.
+    pub fn select(cond: ndbool, then: ndbool, els: ndbool) -> ndbool {
+        NdMachine::with(|machine| machine.select(cond, then, els))
+    }
+    /// Returns `!(self & other)`: one variable and three clauses.
+    pub fn nand(self, other: ndbool) -> ndbool {
+        NdMachine::with(|machine| machine.nand(self, other))
+    }
+    /// Returns `!(self | other)`: one variable and three clauses.
+    pub fn nor(self, other: ndbool) -> ndbool {
+        NdMachine::with(|machine| machine.nor(self, other))
+    }
+    /// Returns `self == other`: one variable and four clauses.
+    pub fn xnor(self, other: ndbool) -> ndbool {
+        NdMachine::with(|machine| machine.xnor(self, other))
+    }
+    /// ANDs `bits` as a balanced tree; `t()` for an empty slice.
+    pub fn all(bits: &[ndbool]) -> ndbool {
+        NdMachine::with(|machine| machine.all(bits))
+    }
+    /// ORs `bits` as a balanced tree; `f()` for an empty slice.
+    pub fn any(bits: &[ndbool]) -> ndbool {
+        NdMachine::with(|machine| machine.any(bits))
+    }
+    /// XORs `bits` as a balanced tree; `f()` for an empty slice.
+    pub fn parity(bits: &[ndbool]) -> ndbool {
+        NdMachine::with(|machine| machine.parity(bits))
     }
 }
 
 impl Not for ndbool {
     type Output = ndbool;
     fn not(self) -> ndbool {
-        ndbool(!self.0)
+        ndbool(!self.0, self.1)
     }
 }
 
 impl BitAnd for ndbool {
     type Output = ndbool;
     fn bitand(self, other: ndbool) -> ndbool {
-        NdMachine::with(|machine| {
-            let l = machine.instance.fresh_var();
-            machine.instance.assert_any(&[!self.0, !other.0, l]);
-            machine.instance.assert_any(&[self.0, !l]);
-            machine.instance.assert_any(&[other.0, !l]);
-            machine.assignment = None;
-            ndbool(l)
-        })
+        NdMachine::with(|machine| machine.and(self, other))
     }
 }
 
@@ -130,14 +850,7 @@ impl BitAndAssign for ndbool {
 impl BitOr for ndbool {
     type Output = ndbool;
     fn bitor(self, other: ndbool) -> ndbool {
-        NdMachine::with(|machine| {
-            let l = machine.instance.fresh_var();
-            machine.instance.assert_any(&[self.0, other.0, !l]);
-            machine.instance.assert_any(&[!self.0, l]);
-            machine.instance.assert_any(&[!other.0, l]);
-            machine.assignment = None;
-            ndbool(l)
-        })
+        NdMachine::with(|machine| machine.or(self, other))
     }
 }
 
@@ -150,7 +863,7 @@ impl BitOrAssign for ndbool {
 impl BitXor for ndbool {
     type Output = ndbool;
     fn bitxor(self, other: ndbool) -> ndbool {
-        (self | other) & !(self & other)
+        NdMachine::with(|machine| machine.xor(self, other))
     }
 }
 
@@ -162,7 +875,7 @@ impl BitXorAssign for ndbool {
 
 impl NdEq for ndbool {
     fn ndeq(&self, rhs: &ndbool) -> ndbool {
-        (*self | !*rhs) & (!*self | *rhs)
+        self.xnor(*rhs)
     }
 }
 
@@ -174,15 +887,166 @@ pub trait NdEq<Rhs : ?Sized = Self> {
     }
 }
 
+/// Symbolic ordering. `ndle`, `ndgt` and `ndge` default to combinations of
+/// `ndlt` and `ndeq`; implementations may override them with cheaper
+/// circuits.
+pub trait NdOrd<Rhs: ?Sized = Self>: NdEq<Rhs> {
+    fn ndlt(&self, rhs: &Rhs) -> ndbool;
+    fn ndle(&self, rhs: &Rhs) -> ndbool {
+        self.ndlt(rhs) | self.ndeq(rhs)
+    }
+    fn ndgt(&self, rhs: &Rhs) -> ndbool {
+        !self.ndle(rhs)
+    }
+    fn ndge(&self, rhs: &Rhs) -> ndbool {
+        !self.ndlt(rhs)
+    }
+}
+
+/// `false < true`.
+impl NdOrd for ndbool {
+    fn ndlt(&self, rhs: &ndbool) -> ndbool {
+        !*self & *rhs
+    }
+    fn ndle(&self, rhs: &ndbool) -> ndbool {
+        self.implies(*rhs)
+    }
+}
+
+impl<T: NdEq, const N: usize> NdEq for [T; N] {
+    fn ndeq(&self, rhs: &[T; N]) -> ndbool {
+        self[..].ndeq(&rhs[..])
+    }
+}
+
+/// Sequences of different lengths are never equal: comparing them yields the
+/// constant `ndbool::f()` rather than panicking.
+impl<T: NdEq> NdEq for [T] {
+    fn ndeq(&self, rhs: &[T]) -> ndbool {
+        if self.len() != rhs.len() {
+            return ndbool::f();
+        }
+        conjunction(self.iter().zip(rhs.iter()).map(|(a, b)| a.ndeq(b)))
+    }
+}
+
+/// Compares element-wise like `[T]`; different lengths yield `ndbool::f()`.
+impl<T: NdEq> NdEq for Vec<T> {
+    fn ndeq(&self, rhs: &Vec<T>) -> ndbool {
+        self[..].ndeq(&rhs[..])
+    }
+}
+
+/// Tuples compare element-wise; the elements on each side may have
+/// different types as long as they are comparable.
+macro_rules! tuple_ndeq {
+    ($(($A:ident, $B:ident, $i:tt)),*) => {
+        impl<$($A: NdEq<$B>, $B),*> NdEq<($($B,)*)> for ($($A,)*) {
+            fn ndeq(&self, rhs: &($($B,)*)) -> ndbool {
+                ndbool::all(&[$(self.$i.ndeq(&rhs.$i)),*])
+            }
+        }
+    };
+}
+
+tuple_ndeq!((A0, B0, 0));
+tuple_ndeq!((A0, B0, 0), (A1, B1, 1));
+tuple_ndeq!((A0, B0, 0), (A1, B1, 1), (A2, B2, 2));
+tuple_ndeq!((A0, B0, 0), (A1, B1, 1), (A2, B2, 2), (A3, B3, 3));
+tuple_ndeq!((A0, B0, 0), (A1, B1, 1), (A2, B2, 2), (A3, B3, 3), (A4, B4, 4));
+tuple_ndeq!((A0, B0, 0), (A1, B1, 1), (A2, B2, 2), (A3, B3, 3), (A4, B4, 4), (A5, B5, 5));
+tuple_ndeq!((A0, B0, 0), (A1, B1, 1), (A2, B2, 2), (A3, B3, 3), (A4, B4, 4), (A5, B5, 5),
+            (A6, B6, 6));
+tuple_ndeq!((A0, B0, 0), (A1, B1, 1), (A2, B2, 2), (A3, B3, 3), (A4, B4, 4), (A5, B5, 5),
+            (A6, B6, 6), (A7, B7, 7));
+tuple_ndeq!((A0, B0, 0), (A1, B1, 1), (A2, B2, 2), (A3, B3, 3), (A4, B4, 4), (A5, B5, 5),
+            (A6, B6, 6), (A7, B7, 7), (A8, B8, 8));
+tuple_ndeq!((A0, B0, 0), (A1, B1, 1), (A2, B2, 2), (A3, B3, 3), (A4, B4, 4), (A5, B5, 5),
+            (A6, B6, 6), (A7, B7, 7), (A8, B8, 8), (A9, B9, 9));
+tuple_ndeq!((A0, B0, 0), (A1, B1, 1), (A2, B2, 2), (A3, B3, 3), (A4, B4, 4), (A5, B5, 5),
+            (A6, B6, 6), (A7, B7, 7), (A8, B8, 8), (A9, B9, 9), (A10, B10, 10));
+tuple_ndeq!((A0, B0, 0), (A1, B1, 1), (A2, B2, 2), (A3, B3, 3), (A4, B4, 4), (A5, B5, 5),
+            (A6, B6, 6), (A7, B7, 7), (A8, B8, 8), (A9, B9, 9), (A10, B10, 10), (A11, B11, 11));
+
+/// Lexicographic order with the first element most significant, like the
+/// `Ord` of Rust slices: a proper prefix is smaller.
+impl<T: NdOrd> NdOrd for [T] {
+    fn ndlt(&self, rhs: &[T]) -> ndbool {
+        // Walk from the first element, tracking whether the prefixes seen
+        // so far are equal.
+        let mut eq = ndbool::t();
+        let mut lt = ndbool::f();
+        for (a, b) in self.iter().zip(rhs.iter()) {
+            lt |= eq & a.ndlt(b);
+            eq &= a.ndeq(b);
+        }
+        if self.len() < rhs.len() {
+            lt |= eq;
+        }
+        lt
+    }
+    fn ndle(&self, rhs: &[T]) -> ndbool {
+        !rhs.ndlt(self)
+    }
+    fn ndgt(&self, rhs: &[T]) -> ndbool {
+        rhs.ndlt(self)
+    }
+}
+
+impl<T: NdOrd, const N: usize> NdOrd for [T; N] {
+    fn ndlt(&self, rhs: &[T; N]) -> ndbool {
+        self[..].ndlt(&rhs[..])
+    }
+    fn ndle(&self, rhs: &[T; N]) -> ndbool {
+        self[..].ndle(&rhs[..])
+    }
+}
+
+impl<T: NdOrd> NdOrd for Vec<T> {
+    fn ndlt(&self, rhs: &Vec<T>) -> ndbool {
+        self[..].ndlt(&rhs[..])
+    }
+    fn ndle(&self, rhs: &Vec<T>) -> ndbool {
+        self[..].ndle(&rhs[..])
+    }
+}
+
+/// Returns `lhs < rhs` in lexicographic order. See `NdOrd for [T]`.
+pub fn lex_lt<T: NdOrd>(lhs: &[T], rhs: &[T]) -> ndbool {
+    lhs.ndlt(rhs)
+}
+
+/// Returns `lhs <= rhs` in lexicographic order.
+pub fn lex_le<T: NdOrd>(lhs: &[T], rhs: &[T]) -> ndbool {
+    lhs.ndle(rhs)
+}
+
+/// ANDs the given literals together; the empty conjunction is true.
+fn conjunction<I: IntoIterator<Item = ndbool>>(bits: I) -> ndbool {
+    ndbool::all(&bits.into_iter().collect::<Vec<_>>())
+}
+
 
 #[cfg(test)]
 mod tests {
     use std::process::Command;
+    use std::thread;
 
     use super::*;
 
-    fn solve() -> bool {
-        solve_by(&sat::solver::Dimacs::new(|| Command::new("minisat")))
+    /// The solver used by the tests: in-process when the `varisat` feature
+    /// is enabled, `minisat` otherwise.
+    #[cfg(feature = "varisat")]
+    pub fn solver() -> impl Solver {
+        VarisatSolver::new()
+    }
+    #[cfg(not(feature = "varisat"))]
+    pub fn solver() -> impl Solver {
+        sat::solver::Dimacs::new(|| Command::new("minisat"))
+    }
+
+    pub fn solve() -> bool {
+        solve_by(&solver())
     }
 
     #[test]
@@ -221,6 +1085,259 @@ mod tests {
         assert!(b0.value() ^ b1.value());
     }
 
+    #[test]
+    fn test_implies() {
+        init();
+        let b0 = ndbool::fresh();
+        let b1 = ndbool::fresh();
+        ndassert(b0.implies(b1));
+        ndassert(b0);
+        assert!(solve());
+        assert!(b1.value());
+        ndassert(!b1);
+        assert!(!solve());
+    }
+
+    #[test]
+    fn test_implies_eq_or() {
+        init();
+        let b0 = ndbool::fresh();
+        let b1 = ndbool::fresh();
+        ndassert_ne(b0.implies(b1), !b0 | b1);
+        assert!(!solve());
+    }
+
+    #[test]
+    fn test_assert_implies() {
+        init();
+        let b0 = ndbool::fresh();
+        let b1 = ndbool::fresh();
+        ndassert_implies(b0, b1);
+        ndassert(!b1);
+        assert!(solve());
+        assert!(!b0.value());
+    }
+
+    #[test]
+    fn test_assert_if() {
+        for &guard_value in &[false, true] {
+            init();
+            let guard = ndbool::fresh();
+            let b0 = ndbool::fresh();
+            let x = ndu::fresh(4);
+            let vars = stats().num_variables;
+            ndassert_if(guard, b0);
+            assert_eq!(stats().num_variables, vars);
+            ndassert_eq_if(guard, x.clone(), ndu::constant(9, 4));
+            ndassert_eq(guard, ndbool::constant(guard_value));
+            ndassert(!b0 | x.ndne(&ndu::constant(9, 4)));
+            assert_eq!(solve(), !guard_value);
+        }
+    }
+
+    #[test]
+    fn test_constant() {
+        init();
+        let b0 = ndbool::fresh();
+        let b1 = ndbool::fresh();
+        ndassert_eq(b0, ndbool::constant(true));
+        ndassert_eq(b1, ndbool::constant(false));
+        assert!(solve());
+        assert!(b0.value() && !b1.value());
+        ndassert(ndbool::constant(false));
+        assert!(!solve());
+    }
+
+    #[test]
+    fn test_t_f_shared() {
+        init();
+        ndassert_ne(ndbool::t(), ndbool::t());
+        assert!(!solve());
+        reset();
+        ndassert_ne(ndbool::f(), ndbool::f());
+        assert!(!solve());
+        reset();
+        ndassert_eq(ndbool::t(), ndbool::f());
+        assert!(!solve());
+    }
+
+    #[test]
+    fn test_negated_gates() {
+        init();
+        let b0 = ndbool::fresh();
+        let b1 = ndbool::fresh();
+        ndassert(b0.nand(b1).ndne(&!(b0 & b1)) | b0.nor(b1).ndne(&!(b0 | b1)) |
+                 b0.xnor(b1).ndne(&!(b0 ^ b1)));
+        assert!(!solve());
+    }
+
+    #[test]
+    fn test_xnor_truth_table() {
+        for &(x, y) in &[(false, false), (false, true), (true, false), (true, true)] {
+            init();
+            let b = ndbool::constant(x).xnor(ndbool::constant(y));
+            assert!(solve());
+            assert_eq!(b.value(), x == y);
+        }
+    }
+
+    #[test]
+    fn test_select_truth_table() {
+        for i in 0..8 {
+            let (c, t, e) = (i & 1 != 0, i & 2 != 0, i & 4 != 0);
+            init();
+            let c0 = ndbool::fresh();
+            let t0 = ndbool::fresh();
+            let e0 = ndbool::fresh();
+            ndassert_eq(c0, ndbool::constant(c));
+            ndassert_eq(t0, ndbool::constant(t));
+            ndassert_eq(e0, ndbool::constant(e));
+            let b = ndbool::select(c0, t0, e0);
+            assert!(solve());
+            assert_eq!(b.value(), if c { t } else { e });
+        }
+    }
+
+    #[test]
+    fn test_array_eq() {
+        init();
+        let xs = [ndbool::fresh(), ndbool::fresh(), ndbool::fresh()];
+        let ys = [ndbool::fresh(), ndbool::fresh(), ndbool::fresh()];
+        ndassert_ne(xs, ys);
+        ndassert_eq(xs[0], ys[0]);
+        ndassert_eq(xs[2], ys[2]);
+        assert!(solve());
+        assert!(xs[1].value() != ys[1].value());
+        ndassert_eq(xs[1], ys[1]);
+        assert!(!solve());
+    }
+
+    #[test]
+    fn test_array_eq_ndu() {
+        init();
+        let xs = [ndu::fresh(4), ndu::fresh(4)];
+        ndassert_eq(xs.clone(), [ndu::constant(3, 4), ndu::constant(12, 4)]);
+        assert!(solve());
+        assert_eq!(xs[0].value(), 3);
+        assert_eq!(xs[1].value(), 12);
+        let empty: [ndbool; 0] = [];
+        ndassert(empty.ndeq(&[]));
+        assert!(solve());
+    }
+
+    #[test]
+    fn test_tuple_eq() {
+        init();
+        let x = ndu::fresh(3);
+        let y = ndu::fresh(3);
+        let p = ndbool::fresh();
+        let q = ndbool::fresh();
+        let r = ndbool::fresh();
+        ndassert_ne((x.clone(), p, r), (y.clone(), q, r));
+        ndassert_eq(x.clone(), y.clone());
+        assert!(solve());
+        assert!(p.value() != q.value());
+        ndassert_eq(p, q);
+        assert!(!solve());
+
+        init();
+        let pair = (ndu::fresh(2), ndbool::fresh());
+        ndassert_eq(pair.clone(), (ndu::constant(2, 2), ndbool::t()));
+        let single = (ndbool::fresh(),);
+        ndassert(single.ndeq(&(ndbool::f(),)));
+        assert!(solve());
+        assert_eq!(pair.0.value(), 2);
+        assert!(pair.1.value());
+        assert!(!single.0.value());
+    }
+
+    #[test]
+    fn test_ndbool_ord() {
+        init();
+        let cases: Vec<_> = [(false, false), (false, true), (true, false), (true, true)].iter().map(|&(a, b)| {
+            let (x, y) = (ndbool::constant(a), ndbool::constant(b));
+            (a, b, x.ndlt(&y), x.ndle(&y), x.ndgt(&y), x.ndge(&y))
+        }).collect();
+        assert!(solve());
+        for (a, b, lt, le, gt, ge) in cases {
+            let (a, b) = (a as u8, b as u8);
+            assert_eq!(lt.value(), a < b);
+            assert_eq!(le.value(), a <= b);
+            assert_eq!(gt.value(), a > b);
+            assert_eq!(ge.value(), a >= b);
+        }
+    }
+
+    #[test]
+    fn test_ndassert_lt_le() {
+        init();
+        let x = ndu::fresh(3);
+        let y = ndu::fresh(3);
+        ndassert_lt(x.clone(), y.clone());
+        ndassert_le(y.clone(), ndu::constant(1, 3));
+        assert!(solve());
+        assert_eq!((x.value(), y.value()), (0, 1));
+        ndassert_lt(ndi::constant(-1, 3), ndi::constant(-2, 3));
+        assert!(!solve());
+    }
+
+    #[test]
+    fn test_lex_order() {
+        init();
+        let seqs: Vec<Vec<u64>> = vec![vec![], vec![0], vec![2], vec![0, 3], vec![1, 0], vec![1, 2], vec![1, 2, 0], vec![3, 3]];
+        let nd: Vec<Vec<ndu>> = seqs.iter().map(|s| s.iter().map(|&v| ndu::constant(v, 2)).collect()).collect();
+        let mut cases = vec![];
+        for (a, x) in seqs.iter().zip(nd.iter()) {
+            for (b, y) in seqs.iter().zip(nd.iter()) {
+                cases.push((a < b, a <= b, lex_lt(x, y), lex_le(x, y)));
+            }
+        }
+        let arrays = [ndbool::t(), ndbool::f()].ndlt(&[ndbool::t(), ndbool::t()]);
+        assert!(solve());
+        for (lt, le, x, y) in cases {
+            assert_eq!(x.value(), lt);
+            assert_eq!(y.value(), le);
+        }
+        assert!(arrays.value());
+    }
+
+    #[test]
+    fn test_lex_symmetry_breaking() {
+        let solver = solver();
+        init();
+        let rows: Vec<Vec<ndbool>> = (0..2).map(|_| (0..2).map(|_| ndbool::fresh()).collect()).collect();
+        let vars: Vec<ndbool> = rows.iter().flat_map(|r| r.iter().cloned()).collect();
+        assert_eq!(count_solutions(&solver, &vars), 16);
+        init();
+        let rows: Vec<Vec<ndbool>> = (0..2).map(|_| (0..2).map(|_| ndbool::fresh()).collect()).collect();
+        let vars: Vec<ndbool> = rows.iter().flat_map(|r| r.iter().cloned()).collect();
+        ndassert(lex_le(&rows[0], &rows[1]));
+        assert_eq!(count_solutions(&solver, &vars), 10);
+    }
+
+    #[test]
+    fn test_vec_eq() {
+        init();
+        let xs: Vec<_> = (0..3).map(|_| ndbool::fresh()).collect();
+        let ys = vec![ndbool::t(), ndbool::f(), ndbool::t()];
+        ndassert_eq(xs.clone(), ys.clone());
+        assert!(solve());
+        assert_eq!(xs.iter().map(|b| b.value()).collect::<Vec<_>>(), vec![true, false, true]);
+        ndassert_ne(xs, ys);
+        assert!(!solve());
+    }
+
+    #[test]
+    fn test_vec_eq_length_mismatch() {
+        init();
+        let xs: Vec<_> = (0..3).map(|_| ndbool::fresh()).collect();
+        let ys: Vec<_> = (0..2).map(|_| ndbool::fresh()).collect();
+        ndassert_ne(xs.clone(), ys);
+        assert!(solve());
+        ndassert(xs[..].ndeq(&xs[..2]));
+        assert!(!solve());
+    }
+
     #[test]
     fn test_and_comm() {
         init();
@@ -258,4 +1375,386 @@ mod tests {
         ndassert_ne((b0 | b1) | b2, b0 | (b1 | b2));
         assert!(!solve());
     }
+
+    #[test]
+    fn test_solve_result() {
+        init();
+        let b0 = ndbool::fresh();
+        ndassert(b0);
+        let solver = solver();
+        assert_eq!(solve_by_result(&solver), SolveResult::Sat);
+        ndassert(!b0);
+        assert_eq!(solve_by_result(&solver), SolveResult::Unsat);
+    }
+
+    #[test]
+    fn test_solve_result_unknown() {
+        init();
+        let b0 = ndbool::fresh();
+        ndassert(b0);
+        let solver = ExternalSolver::new(|| Command::new("false"));
+        assert_eq!(solve_by_result(&solver), SolveResult::Unknown);
+        assert!(last_solve_error().is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "solver failed: cannot run solver `ndmachine-no-such-solver`")]
+    fn test_solve_by_missing_binary() {
+        init();
+        ndassert(ndbool::fresh());
+        solve_by(&ExternalSolver::new(|| Command::new("ndmachine-no-such-solver")));
+    }
+
+    /// Panics on every solve, like a solver with a bug.
+    struct Buggy;
+
+    impl Solver for Buggy {
+        fn solve(&self, _: &Instance) -> Option<Assignment> {
+            panic!("index out of bounds")
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "index out of bounds")]
+    fn test_solve_result_bug_propagates() {
+        init();
+        ndassert(ndbool::fresh());
+        solve_by_result(&Buggy);
+    }
+
+    #[test]
+    fn test_solver_error() {
+        struct Failing;
+        impl Solver for Failing {
+            fn solve(&self, _: &Instance) -> Option<Assignment> {
+                SolverError::raise("out of memory")
+            }
+        }
+        init();
+        ndassert(ndbool::fresh());
+        assert_eq!(solve_by_result(&Failing), SolveResult::Unknown);
+        assert_eq!(last_solve_error().as_deref(), Some("out of memory"));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "did not create it")]
+    fn test_foreign_ndbool() {
+        let mut m0 = NdMachine::new();
+        let mut m1 = NdMachine::new();
+        let b0 = m0.fresh();
+        let b1 = m1.fresh();
+        m1.and(b0, b1);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "did not create it")]
+    fn test_foreign_ndbool_value() {
+        let mut m0 = NdMachine::new();
+        let mut m1 = NdMachine::new();
+        let b0 = m0.fresh();
+        assert!(m1.solve_by(&solver()));
+        m1.try_value(b0);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "did not create it")]
+    fn test_ndbool_after_init() {
+        init();
+        let b0 = ndbool::fresh();
+        init();
+        ndassert(b0);
+    }
+
+    #[test]
+    fn test_own_ndbool() {
+        let mut machine = NdMachine::new();
+        let b0 = machine.fresh();
+        let fork = machine.fork();
+        assert_eq!(fork.name(!b0), None);
+        machine.assert(!b0);
+        assert!(machine.solve_by(&solver()));
+        assert!(!machine.value(b0));
+    }
+
+    #[test]
+    fn test_without_init() {
+        thread::spawn(|| {
+            let b0 = ndbool::fresh();
+            let b1 = ndbool::fresh();
+            ndassert(b0 & !b1);
+            assert!(solve());
+            assert!(b0.value() && !b1.value());
+            init();
+            assert_eq!(stats().num_variables, 0);
+        }).join().unwrap();
+    }
+
+    #[test]
+    fn test_project() {
+        let mut machine = NdMachine::new();
+        let b0 = machine.fresh();
+        let b1 = machine.fresh();
+        assert_eq!(machine.project(&[b0, b1]), None);
+        let x = machine.and(b0, !b1);
+        machine.assert(x);
+        assert!(machine.solve_by(&solver()));
+        assert_eq!(machine.project(&[b0, b1]), Some(vec![true, false]));
+        assert_eq!(machine.project(&[b1, x, b0]), Some(vec![false, true, true]));
+        assert_eq!(machine.project(&[]), Some(vec![]));
+    }
+
+    #[test]
+    fn test_fork() {
+        let solver = solver();
+        let mut parent = NdMachine::new();
+        let b0 = parent.named("b0");
+        let b1 = parent.fresh();
+        let x = parent.xor(b0, b1);
+        parent.assert(x);
+        assert!(parent.solve_by(&solver));
+
+        let mut fork = parent.fork();
+        assert!(fork.try_value(b0).is_none());
+        assert_eq!(fork.name(b0), Some("b0"));
+        fork.assert(b0);
+        fork.assert(b1);
+        assert!(!fork.solve_by(&solver));
+        assert_eq!(fork.stats().num_clauses, parent.stats().num_clauses + 2);
+
+        // The parent keeps its model and constraints.
+        assert!(parent.try_value(b0).is_some());
+        parent.assert(b1);
+        assert!(parent.solve_by(&solver));
+        assert!(!parent.value(b0) && parent.value(b1));
+
+        // Gates shared with the parent are reused in the fork.
+        let mut fork = parent.fork();
+        let vars = fork.stats().num_variables;
+        let y = fork.xor(b0, b1);
+        assert_eq!(fork.stats().num_variables, vars);
+        fork.assert(!y);
+        assert!(!fork.solve_by(&solver));
+    }
+
+    #[test]
+    fn test_trivially_unsat() {
+        // A solver that would report Unknown if it were invoked.
+        let failing = ExternalSolver::new(|| Command::new("false"));
+        init();
+        let b0 = ndbool::fresh();
+        ndassert(b0);
+        ndassert(!b0);
+        assert_eq!(solve_by_result(&failing), SolveResult::Unsat);
+        assert_eq!(last_solve_error(), None);
+
+        init();
+        ndassert(ndbool::f());
+        assert_eq!(solve_by_result(&failing), SolveResult::Unsat);
+
+        init();
+        assert_exactly_one(&[]);
+        assert_eq!(solve_by_result(&failing), SolveResult::Unsat);
+
+        init();
+        let b0 = ndbool::fresh();
+        ndassert(!b0);
+        assert_eq!(solve_under_assumptions(&failing, &[b0]), SolveResult::Unsat);
+        assert_eq!(solve_by_result(&failing), SolveResult::Unknown);
+    }
+
+    #[test]
+    fn test_solve_under_assumptions() {
+        init();
+        let solver = solver();
+        let b0 = ndbool::fresh();
+        let b1 = ndbool::fresh();
+        ndassert(b0 ^ b1);
+        assert_eq!(solve_under_assumptions(&solver, &[b0]), SolveResult::Sat);
+        assert!(b0.value() && !b1.value());
+        assert_eq!(solve_under_assumptions(&solver, &[b0, b1]), SolveResult::Unsat);
+        assert_eq!(solve_under_assumptions(&solver, &[!b0]), SolveResult::Sat);
+        assert!(!b0.value() && b1.value());
+        assert_eq!(solve_by_result(&solver), SolveResult::Sat);
+    }
+
+    #[test]
+    fn test_try_value() {
+        init();
+        let b0 = ndbool::fresh();
+        ndassert(b0);
+        assert_eq!(b0.try_value(), None);
+        assert!(solve());
+        assert_eq!(b0.try_value(), Some(true));
+        ndassert(!b0);
+        assert!(!solve());
+        assert_eq!(b0.try_value(), None);
+    }
+
+    #[test]
+    fn test_reset() {
+        init();
+        let b0 = ndbool::fresh();
+        ndassert(b0);
+        ndassert(!b0);
+        assert!(!solve());
+        reset();
+        let b1 = ndbool::fresh();
+        ndassert(b1);
+        assert!(solve());
+        assert!(b1.value());
+    }
+
+    #[test]
+    fn test_reductions_empty() {
+        init();
+        assert_eq!(ndbool::all(&[]).0, ndbool::t().0);
+        assert_eq!(ndbool::any(&[]).0, ndbool::f().0);
+        assert_eq!(ndbool::parity(&[]).0, ndbool::f().0);
+        let b0 = ndbool::fresh();
+        assert_eq!(ndbool::all(&[b0]).0, b0.0);
+    }
+
+    #[test]
+    fn test_reductions() {
+        for pattern in 0..32 {
+            init();
+            let bits: Vec<_> = (0..5).map(|_| ndbool::fresh()).collect();
+            for (i, &b) in bits.iter().enumerate() {
+                ndassert_eq(b, ndbool::constant(pattern >> i & 1 == 1));
+            }
+            let all = ndbool::all(&bits);
+            let any = ndbool::any(&bits);
+            let parity = ndbool::parity(&bits);
+            assert!(solve());
+            assert_eq!(all.value(), pattern == 31);
+            assert_eq!(any.value(), pattern != 0);
+            assert_eq!(parity.value(), (pattern as u32).count_ones() % 2 == 1);
+        }
+    }
+
+    #[test]
+    fn test_reductions_match_chain() {
+        init();
+        let bits: Vec<_> = (0..7).map(|_| ndbool::fresh()).collect();
+        let chain = bits[1..].iter().fold(bits[0], |acc, &b| acc ^ b);
+        ndassert_ne(ndbool::parity(&bits), chain);
+        assert!(!solve());
+        init();
+        let bits: Vec<_> = (0..7).map(|_| ndbool::fresh()).collect();
+        let chain = bits[1..].iter().fold(bits[0], |acc, &b| acc & b);
+        ndassert_ne(ndbool::all(&bits), chain);
+        assert!(!solve());
+    }
+
+    #[test]
+    fn test_gate_sharing() {
+        init();
+        let b0 = ndbool::fresh();
+        let b1 = ndbool::fresh();
+        let x = b0 & b1;
+        let before = stats();
+        let y = b0 & b1;
+        let z = b1 & b0;
+        assert_eq!(stats(), before);
+        assert_eq!(x.0, y.0);
+        assert_eq!(x.0, z.0);
+        // Different gates over the same inputs stay distinct.
+        assert_ne!((b0 | b1).0, x.0);
+        assert_ne!(ndbool::select(b0, b1, x).0, ndbool::select(b1, b0, x).0);
+        ndassert(x);
+        assert!(solve());
+        assert!(y.value());
+    }
+
+    #[test]
+    fn test_last_model() {
+        let mut machine = NdMachine::new();
+        assert!(machine.last_model().is_none());
+        let b0 = machine.fresh();
+        machine.assert(b0);
+        assert!(machine.solve_by(&solver()));
+        assert!(machine.last_model().is_some());
+        machine.assert(!b0);
+        assert!(!machine.solve_by(&solver()));
+        assert!(machine.last_model().is_none());
+    }
+
+    #[test]
+    fn test_stats() {
+        init();
+        assert_eq!(stats(), Stats::default());
+        let b0 = ndbool::fresh();
+        let b1 = ndbool::fresh();
+        let x = b0 & b1;
+        assert_eq!(stats(), Stats { num_variables: 3, num_clauses: 3, max_clause_len: 3 });
+        ndassert(x);
+        assert_eq!(stats().num_clauses, 4);
+        ndassert_tagged(b0, "b0");
+        assert_eq!(stats(), Stats { num_variables: 4, num_clauses: 6, max_clause_len: 3 });
+        reset();
+        assert_eq!(stats(), Stats::default());
+    }
+
+    #[test]
+    fn test_assert_all_any() {
+        let solver = solver();
+        init();
+        let bits: Vec<ndbool> = (0..3).map(|_| ndbool::fresh()).collect();
+        ndassert_all(&[]);
+        assert_eq!(stats(), Stats { num_variables: 3, num_clauses: 0, max_clause_len: 0 });
+        ndassert_all(&bits[..2]);
+        ndassert_any(&[!bits[0], !bits[1], !bits[2]]);
+        assert_eq!(stats(), Stats { num_variables: 3, num_clauses: 3, max_clause_len: 3 });
+        assert!(solve_by(&solver));
+        assert_eq!(bits.iter().map(|b| b.value()).collect::<Vec<_>>(), vec![true, true, false]);
+        ndassert_any(&[]);
+        assert_eq!(stats().num_clauses, 4);
+        assert_eq!(solve_by_result(&solver), SolveResult::Unsat);
+    }
+
+    #[test]
+    fn test_clauses() {
+        let mut machine = NdMachine::new();
+        let a = machine.fresh();
+        let b = machine.fresh();
+        let x = machine.and(a, b);
+        let (a, b, x) = (machine.lit(a), machine.lit(b), machine.lit(x));
+        let clauses: Vec<&[Lit]> = machine.clauses().collect();
+        assert_eq!(clauses, vec![&[!a, !b, x][..], &[a, !x], &[b, !x]]);
+        let dimacs: Vec<Vec<isize>> = machine.clauses().map(|c| c.iter().map(|l| l.to_dimacs()).collect()).collect();
+        assert_eq!(dimacs, vec![vec![-1, -2, 3], vec![1, -3], vec![2, -3]]);
+        assert_eq!((x.var(), x.is_negated(), (!x).is_negated()), (2, false, true));
+        assert_eq!(machine.clauses().count(), machine.stats().num_clauses);
+    }
+
+    #[test]
+    fn test_explicit_machines() {
+        let solver = solver();
+        let mut m0 = NdMachine::new();
+        let mut m1 = NdMachine::new();
+        let b0 = m0.fresh();
+        let b1 = m1.fresh();
+        m0.assert(b0);
+        m1.assert(!b1);
+        assert!(m0.solve_by(&solver));
+        assert!(m1.solve_by(&solver));
+        assert!(m0.value(b0));
+        assert!(!m1.value(b1));
+    }
+
+    #[test]
+    fn test_explicit_machine_unsat() {
+        let solver = solver();
+        let mut m = NdMachine::new();
+        let b0 = m.fresh();
+        let b1 = m.fresh();
+        let x = m.xor(b0, b1);
+        let y = m.or(b0, b1);
+        m.assert(x);
+        m.assert(!y);
+        assert!(!m.solve_by(&solver));
+    }
 }
@@ -0,0 +1,71 @@
+//! Solving without blocking an async runtime, requiring the `tokio`
+//! feature.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use sat::solver::Solver;
+use tokio::task::{self, JoinHandle};
+
+use {NdMachine, SolveResult};
+
+/// The future returned by `NdMachine::solve_by_async`.
+pub struct SolveFuture {
+    handle: JoinHandle<(NdMachine, SolveResult)>,
+}
+
+impl Future for SolveFuture {
+    type Output = (NdMachine, SolveResult);
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        Pin::new(&mut self.handle).poll(cx).map(|result| result.expect("solver task failed"))
+    }
+}
+
+impl NdMachine {
+    /// Like `solve_by_result`, but runs the solver on tokio's blocking
+    /// thread pool and resolves once it is done. Must be called within a
+    /// tokio runtime.
+    ///
+    /// The machine is moved to the pool thread for the solve and handed
+    /// back by the future with the model in place, so it is never shared
+    /// between threads and needs no locking. The thread-local machine of
+    /// the free functions cannot be solved this way; build the instance
+    /// on an owned `NdMachine` instead.
+    pub fn solve_by_async<T: Solver + Send + 'static>(mut self, solver: T) -> SolveFuture {
+        SolveFuture {
+            handle: task::spawn_blocking(move || {
+                let result = self.solve_by_result(&solver);
+                (self, result)
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::runtime::Builder;
+
+    use tests::solver;
+    use {NdMachine, SolveResult};
+
+    #[test]
+    fn test_solve_by_async() {
+        let runtime = Builder::new_current_thread().build().unwrap();
+        let _guard = runtime.enter();
+        let mut machine = NdMachine::new();
+        let b0 = machine.fresh();
+        let b1 = machine.fresh();
+        let x = machine.xor(b0, b1);
+        machine.assert(x);
+        machine.assert(b1);
+        let (mut machine, result) = runtime.block_on(machine.solve_by_async(solver()));
+        assert_eq!(result, SolveResult::Sat);
+        assert_eq!(machine.project(&[b0, b1]), Some(vec![false, true]));
+
+        machine.assert(b0);
+        let (machine, result) = runtime.block_on(machine.solve_by_async(solver()));
+        assert_eq!(result, SolveResult::Unsat);
+        assert_eq!(machine.try_value(b0), None);
+    }
+}
@@ -0,0 +1,182 @@
+//! Racing several solvers on the same instance.
+
+use std::cell::RefCell;
+use std::io::Cursor;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use sat::{Assignment, Instance};
+use sat::solver::Solver;
+
+use dimacs::{dimacs_format, parse_dimacs};
+use SolverError;
+
+thread_local! {
+    static CANCEL: RefCell<Option<Arc<AtomicBool>>> = const { RefCell::new(None) };
+}
+
+/// The flag set when the portfolio running on this thread no longer needs
+/// an answer. `ExternalSolver` polls it and kills its process.
+pub(crate) fn cancel_flag() -> Option<Arc<AtomicBool>> {
+    CANCEL.with(|cancel| cancel.borrow().clone())
+}
+
+/// Runs several solvers concurrently and returns the first answer.
+///
+/// Each solve spawns one thread per solver, each on its own copy of the
+/// instance. Once an answer arrives, the other threads are told to stop:
+/// `ExternalSolver`s kill their process, while other solvers, such as
+/// in-process ones, run to completion in the background and their answers
+/// are discarded. A solver is only used by one thread at a time, so a
+/// solver still running from an earlier solve joins the next one late.
+///
+/// Solvers that fail with a `SolverError` are ignored as long as another
+/// one answers; if all of them fail, the solve fails with their messages.
+/// Any other panic of a solver is propagated.
+///
+/// ```ignore
+/// let solver = PortfolioSolver::new()
+///     .solver(DimacsBuilder::new("minisat").build()?)
+///     .solver(DimacsBuilder::new("kissat").build()?)
+///     .solver(VarisatSolver::new());
+/// ndmachine::solve_by(&solver);
+/// ```
+#[derive(Default)]
+pub struct PortfolioSolver {
+    solvers: Vec<Arc<Mutex<Box<dyn Solver + Send>>>>,
+}
+
+impl PortfolioSolver {
+    pub fn new() -> Self {
+        PortfolioSolver { solvers: vec![] }
+    }
+    pub fn solver<T: Solver + Send + 'static>(mut self, solver: T) -> Self {
+        self.solvers.push(Arc::new(Mutex::new(Box::new(solver))));
+        self
+    }
+}
+
+impl Solver for PortfolioSolver {
+    fn solve(&self, instance: &Instance) -> Option<Assignment> {
+        assert!(!self.solvers.is_empty(), "PortfolioSolver has no solvers");
+        let mut dimacs = vec![];
+        dimacs_format().write_instance(&mut dimacs, instance);
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel();
+        for solver in &self.solvers {
+            let (solver, cancel, sender) = (solver.clone(), cancel.clone(), sender.clone());
+            let dimacs = dimacs.clone();
+            thread::spawn(move || {
+                let solver = solver.lock().unwrap_or_else(|e| e.into_inner());
+                if cancel.load(Ordering::SeqCst) {
+                    return;
+                }
+                CANCEL.with(|c| *c.borrow_mut() = Some(cancel));
+                let result = panic::catch_unwind(AssertUnwindSafe(|| solver.solve(&read_instance(&dimacs))));
+                let _ = sender.send(result);
+            });
+        }
+        drop(sender);
+        let mut errors = vec![];
+        for result in receiver.iter().take(self.solvers.len()) {
+            match result {
+                Ok(answer) => {
+                    cancel.store(true, Ordering::SeqCst);
+                    return answer;
+                }
+                Err(payload) => match payload.downcast::<SolverError>() {
+                    Ok(error) => errors.push(error.0),
+                    Err(payload) => {
+                        cancel.store(true, Ordering::SeqCst);
+                        panic::resume_unwind(payload);
+                    }
+                },
+            }
+        }
+        SolverError::raise(format!("all solvers of the portfolio failed: {}", errors.join("; ")))
+    }
+}
+
+/// Rebuilds an `Instance` from the DIMACS written by `dimacs_format`.
+fn read_instance(dimacs: &[u8]) -> Instance {
+    let cnf = parse_dimacs(&mut Cursor::new(dimacs)).expect("malformed DIMACS from sat::solver::Dimacs");
+    let mut instance = Instance::new();
+    let vars: Vec<_> = (0..cnf.num_vars).map(|_| instance.fresh_var()).collect();
+    for clause in &cnf.clauses {
+        let lits: Vec<_> = clause.iter().map(|&l| {
+            let v = vars[l.unsigned_abs() - 1];
+            if l < 0 { !v } else { v }
+        }).collect();
+        instance.assert_any(&lits);
+    }
+    instance
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use tests::solver;
+    use {ExternalSolver, NdMachine, SolveResult};
+
+    /// Sleeps, then wrongly answers UNSAT.
+    struct Sleepy(Duration);
+
+    impl Solver for Sleepy {
+        fn solve(&self, _: &Instance) -> Option<Assignment> {
+            thread::sleep(self.0);
+            None
+        }
+    }
+
+    /// Fails immediately.
+    struct Broken;
+
+    impl Solver for Broken {
+        fn solve(&self, _: &Instance) -> Option<Assignment> {
+            SolverError::raise("broken")
+        }
+    }
+
+    fn xor_machine() -> (NdMachine, ::ndbool, ::ndbool) {
+        let mut machine = NdMachine::new();
+        let b0 = machine.fresh();
+        let b1 = machine.fresh();
+        let x = machine.xor(b0, b1);
+        machine.assert(x);
+        machine.assert(b0);
+        (machine, b0, b1)
+    }
+
+    #[test]
+    fn test_portfolio_fast_wins() {
+        let sleep = ExternalSolver::new(|| {
+            let mut cmd = Command::new("sh");
+            cmd.args(["-c", "exec sleep 10", "sh"]);
+            cmd
+        });
+        let portfolio = PortfolioSolver::new()
+            .solver(Sleepy(Duration::from_secs(2)))
+            .solver(sleep)
+            .solver(Broken)
+            .solver(solver());
+        let (mut machine, b0, b1) = xor_machine();
+        let start = Instant::now();
+        assert_eq!(machine.solve_by_result(&portfolio), SolveResult::Sat);
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(machine.value(b0) && !machine.value(b1));
+    }
+
+    #[test]
+    fn test_portfolio_all_fail() {
+        let portfolio = PortfolioSolver::new().solver(Broken).solver(Broken);
+        let (mut machine, _, _) = xor_machine();
+        assert_eq!(machine.solve_by_result(&portfolio), SolveResult::Unknown);
+        assert_eq!(machine.last_error(), Some("all solvers of the portfolio failed: broken; broken"));
+    }
+}
@@ -0,0 +1,94 @@
+//! Callbacks reporting the progress of solves and searches.
+
+use std::time::Duration;
+
+use {NdMachine, SolveResult};
+
+/// An event reported to the callback of `NdMachine::set_progress_callback`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProgressEvent {
+    /// A solve is starting, including those made by searches such as
+    /// `solutions` and `minimize_value`.
+    SolveStarted,
+    SolveFinished { result: SolveResult, elapsed: Duration },
+    /// `add_blocking_clause` excluded the current model.
+    BlockingClauseAdded,
+    /// `minimize_value` found a model with a smaller objective.
+    NewBest { value: u64 },
+}
+
+/// Sets the progress callback of the thread-local machine. See
+/// `NdMachine::set_progress_callback`.
+pub fn set_progress_callback(callback: Box<dyn FnMut(ProgressEvent) + Send>) {
+    NdMachine::with(|machine| machine.set_progress_callback(callback))
+}
+
+impl NdMachine {
+    /// Calls `callback` with the events of later solves and searches,
+    /// replacing any previous callback. It is kept by `reset` but not
+    /// copied by `fork`.
+    ///
+    /// The callback runs while the machine is borrowed, so it must not use
+    /// the thread-local machine through the free functions or operators.
+    pub fn set_progress_callback(&mut self, callback: Box<dyn FnMut(ProgressEvent) + Send>) {
+        self.progress = Some(callback);
+    }
+    /// Removes the progress callback.
+    pub fn clear_progress_callback(&mut self) {
+        self.progress = None;
+    }
+    pub(crate) fn report(&mut self, event: ProgressEvent) {
+        if let Some(ref mut progress) = self.progress {
+            progress(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use tests::solver;
+    use {init, minimize_value, ndassert, ndu, NdOrd};
+
+    #[test]
+    fn test_progress_enumeration() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let mut machine = NdMachine::new();
+        let log = events.clone();
+        machine.set_progress_callback(Box::new(move |event| log.lock().unwrap().push(event)));
+        let a = machine.fresh();
+        let b = machine.fresh();
+        let x = machine.or(a, b);
+        machine.assert(x);
+        assert_eq!(machine.count_solutions_capped(&solver(), &[a, b], 10), (3, true));
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 11);
+        let count = |f: fn(&ProgressEvent) -> bool| events.iter().filter(|e| f(e)).count();
+        assert_eq!(count(|e| *e == ProgressEvent::SolveStarted), 4);
+        assert_eq!(count(|e| matches!(*e, ProgressEvent::SolveFinished { result: SolveResult::Sat, .. })), 3);
+        assert_eq!(count(|e| matches!(*e, ProgressEvent::SolveFinished { result: SolveResult::Unsat, .. })), 1);
+        assert_eq!(count(|e| *e == ProgressEvent::BlockingClauseAdded), 3);
+        assert_eq!(events[0], ProgressEvent::SolveStarted);
+        assert_eq!(events[2], ProgressEvent::BlockingClauseAdded);
+    }
+
+    #[test]
+    fn test_progress_minimize() {
+        init();
+        let events = Arc::new(Mutex::new(vec![]));
+        let log = events.clone();
+        set_progress_callback(Box::new(move |event| {
+            if let ProgressEvent::NewBest { value } = event {
+                log.lock().unwrap().push(value);
+            }
+        }));
+        let x = ndu::fresh(4);
+        ndassert(x.ndge(&ndu::constant(5, 4)));
+        assert_eq!(minimize_value(&solver(), &x, None, None), (Some(5), true));
+        let values = events.lock().unwrap();
+        assert_eq!(values.last(), Some(&5));
+        assert!(values.windows(2).all(|w| w[0] > w[1]));
+    }
+}
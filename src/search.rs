@@ -0,0 +1,469 @@
+use sat::solver::Solver;
+
+use card::weighted_sum;
+use {ndassert_implies, ndbool, ndu, popcount, solve_under_assumptions, NdMachine, NdOrd, ProgressEvent, SolveResult};
+
+/// Iterates over the distinct assignments to `vars` that satisfy the
+/// thread-local machine.
+///
+/// Each step solves the instance and then adds a clause blocking the
+/// projection it found, so the constraints grow as the iterator advances.
+pub fn solutions<'a, T: Solver>(solver: &'a T, vars: &[ndbool]) -> Solutions<'a, T> {
+    Solutions {
+        solver,
+        vars: vars.to_vec(),
+    }
+}
+
+/// Counts the distinct assignments to `vars` that satisfy the thread-local
+/// machine. This may take time exponential in `vars.len()`.
+///
+/// Like `solutions`, this leaves a blocking clause for every solution found.
+pub fn count_solutions<T: Solver>(solver: &T, vars: &[ndbool]) -> usize {
+    count_solutions_capped(solver, vars, usize::MAX).0
+}
+
+/// Counts solutions like `count_solutions` but stops after `cap` of them.
+///
+/// Returns the count and whether it is exact; when the cap is reached or
+/// the solver fails the count is only a lower bound. With a cap of 2,
+/// `(1, true)` means that the constraints determine `vars` uniquely.
+pub fn count_solutions_capped<T: Solver>(solver: &T, vars: &[ndbool], cap: usize) -> (usize, bool) {
+    NdMachine::with(|machine| machine.count_solutions_capped(solver, vars, cap))
+}
+
+/// Finds a model of the thread-local machine with as few of `objective`
+/// true as possible, returning that number, or `None` if no model was
+/// found, and whether the search finished. See `minimize_value`.
+pub fn minimize<T: Solver>(solver: &T, objective: &[ndbool]) -> (Option<usize>, bool) {
+    let (min, optimal) = minimize_value(solver, &popcount(objective), Some(0), None);
+    (min.map(|v| v as usize), optimal)
+}
+
+/// Like `minimize`, but makes as many of `objective` true as possible.
+pub fn maximize<T: Solver>(solver: &T, objective: &[ndbool]) -> (Option<usize>, bool) {
+    let negated: Vec<ndbool> = objective.iter().map(|&b| !b).collect();
+    let (min, optimal) = minimize(solver, &negated);
+    (min.map(|min| objective.len() - min), optimal)
+}
+
+/// Finds a model of the thread-local machine minimizing the unsigned value
+/// of `objective`.
+///
+/// The solver is called repeatedly, each time with the additional constraint
+/// that the objective is smaller than in the previous model, until it answers
+/// UNSAT or fails. The best model is kept as the current assignment and can
+/// be read with `value`.
+///
+/// Returns the best value found, or `None` if no model was found, and
+/// whether the search finished. If it did, the value is optimal, or the
+/// instance is unsatisfiable if there is none. Otherwise the solver failed
+/// (see `last_solve_error`) and the value is only an upper bound.
+///
+/// Each bound is guarded by a fresh selector variable, like a tagged
+/// assertion, and only assumed during the search with
+/// `solve_under_assumptions`. The instance therefore keeps all of its
+/// models afterwards, and an `IncrementalSolver` stays warm throughout.
+///
+/// `lower` and `upper` are optional hints. If the objective reaches `lower`
+/// the search stops and the value is taken as optimal, and `upper` is
+/// assumed from the start. A wrong `lower` hint gives a suboptimal answer
+/// and a wrong `upper` hint makes the search find no model.
+pub fn minimize_value<T: Solver>(solver: &T, objective: &ndu, lower: Option<u64>, upper: Option<u64>) -> (Option<u64>, bool) {
+    let width = objective.width();
+    let mut assumptions = vec![];
+    if let Some(upper) = upper {
+        assumptions.push(guarded(objective.ndle(&ndu::constant(upper, width))));
+    }
+    let hints = assumptions.len();
+    let mut best = None;
+    let finished = loop {
+        match solve_under_assumptions(solver, &assumptions) {
+            SolveResult::Sat => {}
+            SolveResult::Unsat => break true,
+            SolveResult::Unknown => break false,
+        }
+        let value = objective.value();
+        best = Some((value, NdMachine::with(|machine| {
+            machine.report(ProgressEvent::NewBest { value });
+            machine.model.take()
+        })));
+        if lower.is_some_and(|lower| value <= lower) {
+            break true;
+        }
+        assumptions.truncate(hints);
+        assumptions.push(guarded(objective.ndlt(&ndu::constant(value, width))));
+    };
+    let best = best.map(|(value, assignment)| {
+        NdMachine::with(|machine| machine.model = assignment);
+        value
+    });
+    (best, finished)
+}
+
+/// A fresh selector that implies `bound` in the thread-local machine.
+fn guarded(bound: ndbool) -> ndbool {
+    let selector = ndbool::fresh();
+    ndassert_implies(selector, bound);
+    selector
+}
+
+/// Forbids the current values of `vars` in the thread-local machine. See
+/// `NdMachine::add_blocking_clause`.
+pub fn add_blocking_clause(vars: &[ndbool]) {
+    NdMachine::with(|machine| machine.add_blocking_clause(vars))
+}
+
+/// Records `b` as a soft constraint of the thread-local machine, costing
+/// `weight` when violated. See `solve_maxsat`.
+pub fn ndassert_soft(b: ndbool, weight: u64) {
+    NdMachine::with(|machine| machine.assert_soft(b, weight))
+}
+
+/// Finds a model of the thread-local machine minimizing the total weight
+/// of the violated soft constraints, returning that weight, or `None` if
+/// no model was found, and whether the search finished, like
+/// `minimize_value`.
+///
+/// The weights of the violated constraints are summed like in `pb_le` and
+/// the sum is minimized with `minimize_value`, so the bounds of the search
+/// are only assumed and the instance keeps its models.
+pub fn solve_maxsat<T: Solver>(solver: &T) -> (Option<u64>, bool) {
+    let violated: Vec<(u64, ndbool)> = NdMachine::with(|machine| {
+        machine.soft.iter().map(|&(l, w)| (w, !machine.wrap(l))).collect()
+    });
+    let (cost, _) = weighted_sum(&violated);
+    minimize_value(solver, &cost, Some(0), None)
+}
+
+/// Iterator returned by `solutions`.
+pub struct Solutions<'a, T: 'a> {
+    solver: &'a T,
+    vars: Vec<ndbool>,
+}
+
+impl<'a, T: Solver> Iterator for Solutions<'a, T> {
+    type Item = Vec<bool>;
+    fn next(&mut self) -> Option<Vec<bool>> {
+        let solver = self.solver;
+        let vars = &self.vars;
+        NdMachine::with(|machine| machine.next_solution(solver, vars))
+    }
+}
+
+impl NdMachine {
+    /// Records `b` as a soft constraint costing `weight` when violated. It
+    /// adds no clauses, and only `solve_maxsat` takes it into account.
+    pub fn assert_soft(&mut self, b: ndbool, weight: u64) {
+        let l = self.lit(b);
+        self.soft.push((l, weight));
+    }
+    /// See the free function `count_solutions_capped`.
+    pub fn count_solutions_capped<T: Solver>(&mut self, solver: &T, vars: &[ndbool], cap: usize) -> (usize, bool) {
+        let mut count = 0;
+        while count < cap {
+            match self.solve_by_result(solver) {
+                SolveResult::Sat => self.add_blocking_clause(vars),
+                SolveResult::Unsat => return (count, true),
+                SolveResult::Unknown => return (count, false),
+            }
+            count += 1;
+        }
+        (count, false)
+    }
+    /// Solves the instance and returns the values of `vars`, then blocks
+    /// that projection so the next call finds a different one.
+    ///
+    /// Returns `None` once no further solution exists or the solver fails.
+    pub fn next_solution<T: Solver>(&mut self, solver: &T, vars: &[ndbool]) -> Option<Vec<bool>> {
+        if self.solve_by_result(solver) != SolveResult::Sat {
+            return None;
+        }
+        let model: Vec<bool> = vars.iter().map(|&b| self.value(b)).collect();
+        self.add_blocking_clause(vars);
+        Some(model)
+    }
+    /// Adds a clause forbidding the values `vars` have in the current model,
+    /// then discards the model, so the next solve must change at least one
+    /// of them. This is the step `solutions` and `next_solution` repeat.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no current model, like `value`.
+    pub fn add_blocking_clause(&mut self, vars: &[ndbool]) {
+        let blocking: Vec<_> = vars.iter()
+            .map(|&b| if self.value(b) { !b.0 } else { b.0 })
+            .collect();
+        self.add_clause(&blocking);
+        self.model = None;
+        self.report(ProgressEvent::BlockingClauseAdded);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use sat::{Assignment, Instance};
+
+    use super::*;
+    use tests::solver;
+    use SolverError;
+    use {assert_at_most_k, assert_exactly_one, at_least_k, exactly_k, init, last_solve_error, ndassert, ndassert_eq, solve_by, solve_by_result, NdEq};
+
+    /// Answers with `solver` for the first `answers` solves, then fails.
+    struct FailsAfter<T> {
+        solver: T,
+        answers: Cell<usize>,
+    }
+
+    impl<T: Solver> Solver for FailsAfter<T> {
+        fn solve(&self, instance: &Instance) -> Option<Assignment> {
+            if self.answers.get() == 0 {
+                SolverError::raise("gave up");
+            }
+            self.answers.set(self.answers.get() - 1);
+            self.solver.solve(instance)
+        }
+    }
+
+    fn fails_after<T: Solver>(solver: T, answers: usize) -> FailsAfter<T> {
+        FailsAfter { solver, answers: Cell::new(answers) }
+    }
+
+    #[test]
+    fn test_add_blocking_clause() {
+        init();
+        let solver = solver();
+        let x = ndu::fresh(2);
+        ndassert(x.ndne(&ndu::constant(3, 2)));
+        assert!(solve_by(&solver));
+        let first = x.value();
+        add_blocking_clause(&x.bits);
+        assert!(x.bits[0].try_value().is_none());
+        assert!(solve_by(&solver));
+        let second = x.value();
+        assert_ne!(first, second);
+        add_blocking_clause(&x.bits);
+        assert!(solve_by(&solver));
+        add_blocking_clause(&x.bits);
+        assert!(!solve_by(&solver));
+    }
+
+    #[test]
+    #[should_panic(expected = "No solution!")]
+    fn test_add_blocking_clause_no_model() {
+        init();
+        add_blocking_clause(&[ndbool::fresh()]);
+    }
+
+    #[test]
+    fn test_solutions_free() {
+        init();
+        let solver = solver();
+        let vars = [ndbool::fresh(), ndbool::fresh()];
+        let mut all: Vec<_> = solutions(&solver, &vars).collect();
+        all.sort();
+        assert_eq!(all, vec![vec![false, false], vec![false, true], vec![true, false], vec![true, true]]);
+    }
+
+    #[test]
+    fn test_solutions_projection() {
+        init();
+        let solver = solver();
+        let b0 = ndbool::fresh();
+        let b1 = ndbool::fresh();
+        let b2 = ndbool::fresh();
+        ndassert(b0 | b1 | b2);
+        assert_exactly_one(&[b0, b1]);
+        let all: Vec<_> = solutions(&solver, &[b0, b1]).collect();
+        assert_eq!(all.len(), 2);
+        assert_eq!(solutions(&solver, &[b0, b1]).count(), 0);
+    }
+
+    #[test]
+    fn test_solutions_unsat() {
+        init();
+        let solver = solver();
+        let b0 = ndbool::fresh();
+        ndassert(b0);
+        ndassert(!b0);
+        assert_eq!(solutions(&solver, &[b0]).count(), 0);
+    }
+
+    #[test]
+    fn test_count_solutions_one_hot() {
+        let solver = solver();
+        for n in 1..6 {
+            init();
+            let bits: Vec<_> = (0..n).map(|_| ndbool::fresh()).collect();
+            assert_exactly_one(&bits);
+            assert_eq!(count_solutions(&solver, &bits), n);
+        }
+    }
+
+    #[test]
+    fn test_count_solutions_capped() {
+        init();
+        let solver = solver();
+        let bits: Vec<_> = (0..5).map(|_| ndbool::fresh()).collect();
+        ndassert(exactly_k(&bits, 2));
+        assert_eq!(count_solutions_capped(&solver, &bits, 3), (3, false));
+        assert_eq!(count_solutions_capped(&solver, &bits, 100), (7, true));
+
+        init();
+        let free: Vec<_> = (0..3).map(|_| ndbool::fresh()).collect();
+        assert_eq!(count_solutions_capped(&solver, &free, 4), (4, false));
+
+        init();
+        let x = ndu::fresh(3);
+        ndassert((x.clone() + ndu::constant(3, 3)).eq_const(1));
+        let bits: Vec<_> = x.bits().collect();
+        assert_eq!(count_solutions_capped(&solver, &bits, 2), (1, true));
+    }
+
+    #[test]
+    fn test_count_solutions_capped_failure() {
+        init();
+        let bits: Vec<_> = (0..2).map(|_| ndbool::fresh()).collect();
+        ndassert(bits[0]);
+        assert_eq!(count_solutions_capped(&fails_after(solver(), 1), &bits, 2), (1, false));
+        assert_eq!(count_solutions_capped(&fails_after(solver(), 0), &bits, 2), (0, false));
+    }
+
+    #[test]
+    fn test_minimize() {
+        init();
+        let solver = solver();
+        let bits: Vec<_> = (0..6).map(|_| ndbool::fresh()).collect();
+        ndassert(bits[0] | bits[1]);
+        ndassert(bits[1] | bits[2]);
+        ndassert(bits[3] | bits[4] | bits[5]);
+        assert_eq!(minimize(&solver, &bits), (Some(2), true));
+        assert!(bits[1].value());
+        assert_eq!(bits.iter().filter(|b| b.value()).count(), 2);
+    }
+
+    #[test]
+    fn test_maximize() {
+        init();
+        let solver = solver();
+        let bits: Vec<_> = (0..6).map(|_| ndbool::fresh()).collect();
+        assert_at_most_k(&bits, 4);
+        ndassert(!bits[0]);
+        assert_eq!(maximize(&solver, &bits), (Some(4), true));
+        assert!(!bits[0].value());
+        assert_eq!(bits.iter().filter(|b| b.value()).count(), 4);
+    }
+
+    #[test]
+    fn test_minimize_unsat() {
+        init();
+        let solver = solver();
+        let bits: Vec<_> = (0..3).map(|_| ndbool::fresh()).collect();
+        ndassert(at_least_k(&bits, 2));
+        assert_at_most_k(&bits, 1);
+        assert_eq!(minimize(&solver, &bits), (None, true));
+    }
+
+    #[test]
+    fn test_solve_maxsat() {
+        init();
+        let solver = solver();
+        let b0 = ndbool::fresh();
+        let b1 = ndbool::fresh();
+        ndassert(!(b0 & b1));
+        ndassert_soft(b0, 2);
+        ndassert_soft(b1, 5);
+        assert_eq!(solve_maxsat(&solver), (Some(2), true));
+        assert!(!b0.value() && b1.value());
+
+        init();
+        let bits: Vec<_> = (0..4).map(|_| ndbool::fresh()).collect();
+        assert_at_most_k(&bits, 2);
+        for (i, &b) in bits.iter().enumerate() {
+            ndassert_soft(b, 1 << i);
+        }
+        ndassert_soft(!bits[3], 3);
+        // Taking bits 2 and 3 violates weights 1, 2 and 3.
+        assert_eq!(solve_maxsat(&solver), (Some(6), true));
+        let values: Vec<_> = bits.iter().map(|b| b.value()).collect();
+        assert_eq!(values, vec![false, false, true, true]);
+    }
+
+    #[test]
+    fn test_solve_maxsat_repeated() {
+        init();
+        let solver = solver();
+        let b0 = ndbool::fresh();
+        let b1 = ndbool::fresh();
+        ndassert(!(b0 & b1));
+        ndassert_soft(b0, 2);
+        ndassert_soft(b1, 5);
+        assert_eq!(solve_maxsat(&solver), (Some(2), true));
+        assert!(solve_by(&solver));
+        assert_eq!(solve_maxsat(&solver), (Some(2), true));
+        ndassert(!b1);
+        assert_eq!(solve_maxsat(&solver), (Some(5), true));
+        assert!(b0.value());
+    }
+
+    #[test]
+    fn test_solve_maxsat_hard() {
+        init();
+        let solver = solver();
+        let b0 = ndbool::fresh();
+        ndassert_soft(b0, 1);
+        assert_eq!(solve_maxsat(&solver), (Some(0), true));
+        assert!(b0.value());
+        init();
+        let b0 = ndbool::fresh();
+        ndassert(b0 ^ b0);
+        ndassert_soft(b0, 1);
+        assert_eq!(solve_maxsat(&solver), (None, true));
+    }
+
+    #[test]
+    fn test_minimize_value() {
+        init();
+        let solver = solver();
+        let x = ndu::fresh(4);
+        ndassert(x.ndge(&ndu::constant(5, 4)));
+        assert_eq!(minimize_value(&solver, &x, None, None), (Some(5), true));
+        assert_eq!(x.value(), 5);
+        assert!(solve_by(&solver));
+        ndassert(x.ndge(&ndu::constant(9, 4)));
+        assert_eq!(minimize_value(&solver, &x, None, Some(3)), (None, true));
+        assert_eq!(minimize_value(&solver, &x, None, Some(12)), (Some(9), true));
+    }
+
+    #[test]
+    fn test_minimize_value_failure() {
+        init();
+        let x = ndu::fresh(4);
+        ndassert(x.ndge(&ndu::constant(5, 4)));
+        let (best, optimal) = minimize_value(&fails_after(solver(), 1), &x, None, None);
+        assert!(!optimal && best.unwrap() >= 5);
+        assert_eq!(x.value(), best.unwrap());
+        assert!(last_solve_error().is_some());
+
+        init();
+        ndassert(ndbool::fresh());
+        assert_eq!(minimize(&fails_after(solver(), 0), &[]), (None, false));
+        assert_eq!(maximize(&fails_after(solver(), 0), &[]), (None, false));
+    }
+
+    #[test]
+    fn test_minimize_value_hints() {
+        init();
+        let solver = solver();
+        let x = ndu::fresh(8);
+        let y = ndu::fresh(8);
+        ndassert(x.ndge(&ndu::constant(20, 8)));
+        ndassert(x.ndle(&ndu::constant(200, 8)));
+        ndassert_eq(y.clone(), x.clone() + ndu::constant(3, 8));
+        assert_eq!(minimize_value(&solver, &y, Some(23), Some(100)), (Some(23), true));
+        assert_eq!(x.value(), 20);
+        assert!(solve_by_result(&solver) == SolveResult::Sat);
+    }
+}
@@ -0,0 +1,97 @@
+//! A machine that can be used from several threads.
+
+use std::sync::{Arc, Mutex};
+
+use sat::solver::Solver;
+
+use {ndbool, NdMachine, SolveResult};
+
+/// A handle to an `NdMachine` behind a mutex, which can be cloned and sent
+/// to other threads. Every method locks the machine for its duration, so
+/// calls from different threads are serialized; use `with` to perform
+/// several operations under one lock.
+///
+/// `ndbool`s are plain literals and can be sent between the threads freely.
+/// A solve holds the lock until the solver returns, blocking the other
+/// threads; to race several solvers, `fork` the machine for each of them.
+#[derive(Clone, Default)]
+pub struct SharedNdMachine {
+    machine: Arc<Mutex<NdMachine>>,
+}
+
+impl SharedNdMachine {
+    pub fn new() -> Self {
+        SharedNdMachine::from(NdMachine::new())
+    }
+    /// Runs `f` on the machine under the lock.
+    ///
+    /// Panics if another thread panicked while holding the lock.
+    pub fn with<R, F: FnOnce(&mut NdMachine) -> R>(&self, f: F) -> R {
+        f(&mut self.machine.lock().expect("SharedNdMachine poisoned"))
+    }
+    /// A copy of the machine, like `NdMachine::fork`.
+    pub fn fork(&self) -> NdMachine {
+        self.with(|machine| machine.fork())
+    }
+    pub fn fresh(&self) -> ndbool {
+        self.with(|machine| machine.fresh())
+    }
+    pub fn assert(&self, b: ndbool) {
+        self.with(|machine| machine.assert(b))
+    }
+    pub fn solve_by_result<T: Solver>(&self, solver: &T) -> SolveResult {
+        self.with(|machine| machine.solve_by_result(solver))
+    }
+    /// Returns `None` when there is no current model.
+    pub fn try_value(&self, b: ndbool) -> Option<bool> {
+        self.with(|machine| machine.try_value(b))
+    }
+}
+
+impl From<NdMachine> for SharedNdMachine {
+    fn from(machine: NdMachine) -> Self {
+        SharedNdMachine { machine: Arc::new(Mutex::new(machine)) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use tests::solver;
+
+    #[test]
+    fn test_shared_machine() {
+        let shared = SharedNdMachine::new();
+        let bits: Vec<ndbool> = (0..8).map(|_| shared.fresh()).collect();
+        let workers: Vec<_> = [0, 1].iter().map(|&parity| {
+            let shared = shared.clone();
+            let bits = bits.clone();
+            thread::spawn(move || {
+                for (i, &b) in bits.iter().enumerate() {
+                    if i % 2 == parity {
+                        let x = shared.with(|machine| machine.xor(b, bits[(i + 1) % 8]));
+                        shared.assert(x);
+                    }
+                }
+            })
+        }).collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        shared.assert(bits[0]);
+        assert_eq!(shared.solve_by_result(&solver()), SolveResult::Sat);
+        for (i, &b) in bits.iter().enumerate() {
+            assert_eq!(shared.try_value(b), Some(i % 2 == 0));
+        }
+        assert_eq!(shared.fork().stats(), shared.with(|machine| machine.stats()));
+    }
+
+    #[test]
+    fn test_shared_machine_send_sync() {
+        fn check<T: Send + Sync>() {}
+        check::<ndbool>();
+        check::<SharedNdMachine>();
+    }
+}
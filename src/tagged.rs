@@ -0,0 +1,172 @@
+use sat::solver::Solver;
+
+use {ndbool, Lit, NdMachine, SolveResult};
+
+/// Asserts `b` under the name `tag` on the thread-local machine.
+/// See `NdMachine::assert_tagged`.
+pub fn ndassert_tagged(b: ndbool, tag: &str) {
+    NdMachine::with(|machine| machine.assert_tagged(b, tag))
+}
+
+/// Removes the assertions tagged `tag` from the thread-local machine. See
+/// `NdMachine::retract`.
+pub fn retract(tag: &str) -> bool {
+    NdMachine::with(|machine| machine.retract(tag))
+}
+
+/// Returns a minimal set of tags whose assertions conflict on the
+/// thread-local machine. See `NdMachine::unsat_core`.
+pub fn unsat_core<T: Solver>(solver: &T) -> Vec<String> {
+    NdMachine::with(|machine| machine.unsat_core(solver))
+}
+
+impl NdMachine {
+    /// Asserts `b` under the name `tag`.
+    ///
+    /// The assertion is guarded by a selector variable shared by all
+    /// assertions with the same tag, and every solve assumes the selectors
+    /// true. Tagged assertions therefore behave like `assert`, but can be
+    /// reported by `unsat_core`.
+    pub fn assert_tagged(&mut self, b: ndbool, tag: &str) {
+        let l = self.lit(b);
+        let selector = self.selector(tag);
+        self.add_clause(&[!selector, l]);
+    }
+    /// Removes the assertions tagged `tag`, returning whether there were
+    /// any.
+    ///
+    /// Clauses cannot be deleted, so the selector of the tag is asserted
+    /// false instead, which satisfies the guarded clauses. Asserting under
+    /// the same tag again starts a new selector.
+    pub fn retract(&mut self, tag: &str) -> bool {
+        match self.tags.iter().position(|(t, _)| t == tag) {
+            Some(i) => {
+                let (_, selector) = self.tags.remove(i);
+                self.add_clause(&[!selector]);
+                self.model = None;
+                true
+            }
+            None => false,
+        }
+    }
+    fn selector(&mut self, tag: &str) -> Lit {
+        if let Some(&(_, selector)) = self.tags.iter().find(|&(t, _)| t == tag) {
+            return selector;
+        }
+        let selector = self.fresh_lit();
+        self.tags.push((tag.to_string(), selector));
+        selector
+    }
+    /// Returns a minimal set of tags whose assertions, together with the
+    /// untagged constraints, are unsatisfiable.
+    ///
+    /// The result is empty if the instance is satisfiable (or the solver
+    /// fails), and also if the untagged constraints conflict on their own.
+    ///
+    /// `sat::Solver` does not report cores, so this drops one tag at a time
+    /// and keeps it out whenever the rest stays UNSAT. That takes one solve
+    /// per tag. The current model is cleared afterwards.
+    pub fn unsat_core<T: Solver>(&mut self, solver: &T) -> Vec<String> {
+        let mut core: Vec<usize> = (0..self.tags.len()).collect();
+        if self.solve_with_tags(solver, &core) != SolveResult::Unsat {
+            self.model = None;
+            return vec![];
+        }
+        let mut i = 0;
+        while i < core.len() {
+            let mut trial = core.clone();
+            trial.remove(i);
+            if self.solve_with_tags(solver, &trial) == SolveResult::Unsat {
+                core = trial;
+            } else {
+                i += 1;
+            }
+        }
+        self.model = None;
+        core.into_iter().map(|i| self.tags[i].0.clone()).collect()
+    }
+    fn solve_with_tags<T: Solver>(&mut self, solver: &T, tags: &[usize]) -> SolveResult {
+        let units: Vec<Lit> = tags.iter().map(|&i| self.tags[i].1).collect();
+        self.solve_with_units_only(solver, &units)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tests::{solve, solver};
+    use {init, ndassert};
+
+    #[test]
+    fn test_tagged_assertions_hold() {
+        init();
+        let b0 = ndbool::fresh();
+        let b1 = ndbool::fresh();
+        ndassert_tagged(b0, "b0");
+        ndassert_tagged(!b1, "not b1");
+        assert!(solve());
+        assert!(b0.value() && !b1.value());
+        ndassert_tagged(b1, "b1");
+        assert!(!solve());
+    }
+
+    #[test]
+    fn test_retract() {
+        init();
+        let b0 = ndbool::fresh();
+        let b1 = ndbool::fresh();
+        ndassert(b0 | b1);
+        ndassert_tagged(!b0, "not b0");
+        ndassert_tagged(!b1, "not b1");
+        assert!(!solve());
+        assert!(retract("not b1"));
+        assert!(!retract("not b1"));
+        assert!(solve());
+        assert!(!b0.value() && b1.value());
+
+        ndassert_tagged(!b1, "not b1");
+        assert!(!solve());
+        assert_eq!(unsat_core(&solver()).len(), 2);
+        assert!(retract("not b0"));
+        assert!(solve());
+        assert!(b0.value() && !b1.value());
+    }
+
+    #[test]
+    fn test_unsat_core() {
+        init();
+        let solver = solver();
+        let b0 = ndbool::fresh();
+        let b1 = ndbool::fresh();
+        let b2 = ndbool::fresh();
+        ndassert_tagged(b0, "b0");
+        ndassert_tagged(b1 | b2, "b1 or b2");
+        ndassert_tagged(!b1, "not b1");
+        ndassert_tagged(b0.implies(b1), "b0 implies b1");
+        ndassert_tagged(b2, "b2");
+        let mut core = unsat_core(&solver);
+        core.sort();
+        assert_eq!(core, vec!["b0", "b0 implies b1", "not b1"]);
+    }
+
+    #[test]
+    fn test_unsat_core_sat() {
+        init();
+        let solver = solver();
+        let b0 = ndbool::fresh();
+        ndassert_tagged(b0, "b0");
+        assert!(unsat_core(&solver).is_empty());
+        ndassert(!b0);
+        assert_eq!(unsat_core(&solver), vec!["b0"]);
+    }
+
+    #[test]
+    fn test_shared_tag() {
+        init();
+        let solver = solver();
+        let b0 = ndbool::fresh();
+        ndassert_tagged(b0, "x");
+        ndassert_tagged(!b0, "x");
+        assert_eq!(unsat_core(&solver), vec!["x"]);
+    }
+}